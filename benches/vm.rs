@@ -0,0 +1,51 @@
+//! Compares evaluating the same expression many times with different inputs by walking the
+//! tree, by running the bytecode VM, and by calling a program with declared parameters.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use complexpr::*;
+
+const EXPRS: &[(&str, &str)] = &[
+    ("polynomial", "a*x^2 + b*x + c"),
+    ("builtins", "sin(x)*cos(x/2) + sqrt(abs(x)) - max(x, 1)"),
+    ("chain", "y = x * 2; z = y + 1; 0 <= z < 100"),
+];
+
+fn setup() -> Context {
+    let mut ctx = ctx_default();
+    eval("a = 2.5; b = -1; c = 0.25", &mut ctx).unwrap();
+    ctx
+}
+
+fn bench_eval(c: &mut Criterion) {
+    let mut group = c.benchmark_group("eval");
+    for (name, expr) in EXPRS {
+        let node = compile(expr).unwrap();
+        let mut ctx = setup();
+        let mut x = 0.0;
+        group.bench_function(BenchmarkId::new("tree", name), |b| b.iter(|| {
+            x += 0.01;
+            ctx.insert("x".to_owned(), Value::Float(x));
+            node.eval(&mut ctx).unwrap()
+        }));
+
+        let program = vm::Program::compile(&node);
+        let slot = program.slot("x").unwrap();
+        let mut vm = program.vm(&setup());
+        let mut x = 0.0;
+        group.bench_function(BenchmarkId::new("vm", name), |b| b.iter(|| {
+            x += 0.01;
+            vm.set(slot, Value::Float(x));
+            vm.run().unwrap()
+        }));
+    }
+    let mut f = compile_params(EXPRS[0].1, &["x"], &setup()).unwrap();
+    let mut x = 0.0;
+    group.bench_function(BenchmarkId::new("params", EXPRS[0].0), |b| b.iter(|| {
+        x += 0.01;
+        f.call_f64(&[x]).unwrap()
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, bench_eval);
+criterion_main!(benches);
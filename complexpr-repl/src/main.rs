@@ -52,7 +52,7 @@ fn main() {
                 match result {
                     Ok(Value::Void) => (),
                     Ok(value) => {
-                        println!("{:?}", value);
+                        println!("{}", format::with_display(&ctx, || format!("{:?}", value)));
                         ctx.insert("_".to_owned(), value);
                     },
                     Err(e) => println!("{}{}Error: {}{}", RESET, ERROR, RESET, e)
@@ -174,9 +174,8 @@ impl Completer for CHelper {
             }
         }
         let res: String = res.chars().rev().collect();
-        let mut keys = self.ctx.lock().unwrap().keys()
+        let mut keys = self.ctx.lock().unwrap().keys().into_iter()
             .filter(|x| x.starts_with(&res))
-            .cloned()
             .collect::<Vec<String>>();
         keys.sort();
         Ok((pos - res.len(), keys))
@@ -0,0 +1,355 @@
+//! Variable scopes. A [`Context`] is a chain of frames: blocks in braces and lambda calls push
+//! a new frame, and the variables defined in it disappear when it is popped again.
+//!
+//! Each context also has its own display format for numbers, see [`Context::set_display`].
+//!
+//! Lambdas share frames with the context they were created in rather than copying them (see
+//! [`Captured`]), so a lambda sees later changes to the variables it uses. A lambda call runs in
+//! a frame of its own (see [`Context::push_call_frame`]), and assignments in the lambda body
+//! create variables in that frame instead of changing the variables the lambda was created
+//! with, which can only be changed through references. [`Context::share`] creates another
+//! context with the same frames, while cloning a context copies its frames.
+//!
+//! A lambda stored in a variable would keep the frame holding that variable alive, and so
+//! itself, so it refers to that frame, and the frames below it, only weakly while it is stored
+//! there. Reading the variable gives a lambda that refers to them normally again. This is done
+//! for lambdas in variables and in lists, but not for lambdas held by references or by functions
+//! such as the ones returned by `memoize`, which can still keep their frames alive forever: in
+//! `r = ref(0); r := :(deref(r))` the frame holds the reference, which holds the lambda, which
+//! holds the frame, and none of them is ever freed.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Weak, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use crate::Value;
+use crate::format::{DisplayCell, FormatSpec};
+
+#[derive(Debug)]
+struct FrameData {
+    vars: RwLock<HashMap<String, Value>>,
+    /// Incremented on every change to `vars`
+    version: AtomicU64,
+    /// Whether a value in `vars` may hold a weak reference to a frame
+    weak: AtomicBool
+}
+
+type Frame = Arc<FrameData>;
+
+fn new_frame(vars: HashMap<String, Value>) -> Frame {
+    Arc::new(FrameData{vars: RwLock::new(vars), version: AtomicU64::new(0), weak: AtomicBool::new(false)})
+}
+
+impl FrameData {
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, HashMap<String, Value>> {
+        self.version.fetch_add(1, Ordering::Relaxed);
+        self.vars.write().unwrap()
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, HashMap<String, Value>> {
+        self.vars.read().unwrap()
+    }
+
+    /// Prepare a value read from this frame to be used outside of it
+    fn load(&self, mut value: Value) -> Value {
+        if self.weak.load(Ordering::Relaxed) {
+            strengthen(&mut value);
+        }
+        value
+    }
+}
+
+#[derive(Clone, Debug)]
+enum FrameRef {
+    Strong(Frame),
+    Weak(Weak<FrameData>)
+}
+
+
+/// The frames of a context captured by a lambda, see [`Context::capture`]. Cloning shares them.
+#[derive(Clone, Debug)]
+pub struct Captured {
+    frames: Vec<FrameRef>,
+    display: DisplayCell
+}
+
+impl Captured {
+    /// A context with the captured frames, to call the lambda in. Frames that no longer exist
+    /// are left out, so their variables are unset.
+    pub fn context(&self) -> Context {
+        Context {
+            frames: self.frames.iter().filter_map(|f| match f {
+                FrameRef::Strong(f) => Some(f.clone()),
+                FrameRef::Weak(f) => f.upgrade()
+            }).collect(),
+            local: 0,
+            display: self.display.clone()
+        }
+    }
+}
+
+/// Applies `f` to the captured frames of every lambda in a value, including lambdas in lists.
+/// Returns whether `f` returned true for any of them.
+fn visit_lambdas(value: &mut Value, f: &mut impl FnMut(&mut Captured) -> bool) -> bool {
+    match value {
+        Value::Lambda{ctx, ..} => f(ctx),
+        Value::List(l) => l.iter_mut().fold(false, |res, v| visit_lambdas(v, f) | res),
+        _ => false
+    }
+}
+
+/// Makes the references of lambdas in a value to these frames weak
+fn weaken(value: &mut Value, frames: &[Frame]) -> bool {
+    visit_lambdas(value, &mut |captured| {
+        let mut changed = false;
+        for r in &mut captured.frames {
+            if let FrameRef::Strong(f) = r {
+                if frames.iter().any(|frame| Arc::ptr_eq(f, frame)) {
+                    *r = FrameRef::Weak(Arc::downgrade(f));
+                    changed = true;
+                }
+            }
+        }
+        changed
+    })
+}
+
+/// Makes the weak references of lambdas in a value strong again, if the frames still exist
+fn strengthen(value: &mut Value) {
+    visit_lambdas(value, &mut |captured| {
+        for r in &mut captured.frames {
+            if let FrameRef::Weak(f) = r {
+                if let Some(f) = f.upgrade() {
+                    *r = FrameRef::Strong(f);
+                }
+            }
+        }
+        false
+    });
+}
+
+/// The variables visible to an expression. Lookups search the frames from the innermost to the
+/// outermost, so an inner variable shadows an outer one with the same name.
+///
+/// Cloning a context copies its frames, and the frames captured by the lambdas in them, so
+/// changes to one context do not affect the other. Use [`Context::share`] for a context that
+/// shares the frames instead.
+#[derive(Debug)]
+pub struct Context {
+    frames: Vec<Frame>,
+    /// The index of the outermost frame whose variables assignments change
+    local: usize,
+    /// The format used to display numbers, see [`Context::set_display`]
+    display: DisplayCell
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for Context {
+    fn clone(&self) -> Self {
+        let display = Arc::new(RwLock::new(self.display()));
+        let mut copier = Copier { copies: vec![], display: (self.display.clone(), display.clone()) };
+        Self { frames: self.frames.iter().map(|f| copier.copy(f)).collect(), local: self.local, display }
+    }
+}
+
+/// Copies frames, along with the frames captured by lambdas in them, so that lambdas in the
+/// copies refer to other copies
+struct Copier {
+    /// Each frame copied so far, and its copy
+    copies: Vec<(Frame, Frame)>,
+    /// The display format of the context, and its copy
+    display: (DisplayCell, DisplayCell)
+}
+
+impl Copier {
+    fn copy(&mut self, frame: &Frame) -> Frame {
+        if let Some((_, copy)) = self.copies.iter().find(|(f, _)| Arc::ptr_eq(f, frame)) {
+            return copy.clone()
+        }
+        let copy = new_frame(HashMap::new());
+        copy.weak.store(frame.weak.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.copies.push((frame.clone(), copy.clone()));
+        let mut vars = frame.read().clone();
+        for value in vars.values_mut() {
+            visit_lambdas(value, &mut |captured| {
+                if Arc::ptr_eq(&captured.display, &self.display.0) {
+                    captured.display = self.display.1.clone();
+                }
+                for r in &mut captured.frames {
+                    *r = match r {
+                        FrameRef::Strong(f) => FrameRef::Strong(self.copy(f)),
+                        FrameRef::Weak(f) => match f.upgrade() {
+                            Some(f) => FrameRef::Weak(Arc::downgrade(&self.copy(&f))),
+                            None => FrameRef::Weak(Weak::new())
+                        }
+                    }
+                }
+                false
+            });
+        }
+        *copy.vars.write().unwrap() = vars;
+        copy
+    }
+}
+
+impl Context {
+    /// Create a context with a single empty frame.
+    pub fn new() -> Self {
+        Self { frames: vec![new_frame(HashMap::new())], local: 0, display: Default::default() }
+    }
+
+    /// Create a context with the same frames as this one, so changes to the variables of either
+    /// are visible in both. Frames pushed or popped later are not shared.
+    pub fn share(&self) -> Self {
+        Self { frames: self.frames.clone(), local: self.local, display: self.display.clone() }
+    }
+
+    /// The format used to display floats and complex numbers while evaluating in this context.
+    pub fn display(&self) -> FormatSpec {
+        self.display.read().unwrap().clone()
+    }
+
+    /// Sets the format used whenever floats and complex numbers are converted to strings while
+    /// evaluating in this context, including by `to_str`, `print`, interpolated strings, and
+    /// [`Debug`](std::fmt::Debug). Only the sign, separator, precision, type, and polar options
+    /// are used, and integers and ratios are not affected. Contexts created with
+    /// [`Context::share`] and lambdas created in this context use the same setting. Use
+    /// [`crate::format::with_display`] to apply it outside of evaluation.
+    pub fn set_display(&self, spec: FormatSpec) {
+        *self.display.write().unwrap() = spec;
+    }
+
+    pub(crate) fn display_cell(&self) -> &DisplayCell {
+        &self.display
+    }
+
+    /// The frames a lambda with these free variables needs, shared with this context. `None`
+    /// means the variables cannot be known in advance, and every frame is captured, as it is if
+    /// one of the variables is not defined yet (for example the name of a recursive lambda that
+    /// is being assigned). Otherwise only the frames defining the variables are captured.
+    pub fn capture(&self, names: Option<&[String]>) -> Captured {
+        let names = names.filter(|names| names.iter().all(|n| self.contains_key(n)));
+        let frames = self.frames.iter().filter(|f| match names {
+            Some(names) => {
+                let f = f.read();
+                names.iter().any(|n| f.contains_key(n))
+            },
+            None => true
+        });
+        Captured {
+            frames: frames.map(|f| FrameRef::Strong(f.clone())).collect(),
+            display: self.display.clone()
+        }
+    }
+
+    fn index_of(&self, name: &str) -> Option<usize> {
+        self.frames.iter().rposition(|f| f.read().contains_key(name))
+    }
+
+    /// The value of the innermost variable with this name.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        let frame = &self.frames[self.index_of(name)?];
+        let value = frame.read().get(name).cloned()?;
+        Some(frame.load(value))
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.index_of(name).is_some()
+    }
+
+    /// Store a value in the frame at index `i`
+    fn store(&mut self, i: usize, name: String, mut value: Value) {
+        let frame = &self.frames[i];
+        if weaken(&mut value, &self.frames[..=i]) {
+            frame.weak.store(true, Ordering::Relaxed);
+        }
+        frame.write().insert(name, value);
+    }
+
+    /// Assign to a variable, in the innermost frame that defines it. A variable that is not
+    /// defined anywhere, or only outside the current lambda call, is defined in the innermost
+    /// frame.
+    pub fn insert(&mut self, name: String, value: Value) {
+        let i = self.index_of(&name).filter(|&i| i >= self.local).unwrap_or(self.frames.len() - 1);
+        self.store(i, name, value);
+    }
+
+    /// Define a variable in the innermost frame, shadowing any outer variable with that name.
+    pub fn declare(&mut self, name: String, value: Value) {
+        self.store(self.frames.len() - 1, name, value);
+    }
+
+    /// Remove the innermost variable with this name, returning its value. An outer variable with
+    /// the same name becomes visible again.
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        let frame = &self.frames[self.index_of(name)?];
+        let value = frame.write().remove(name)?;
+        Some(frame.load(value))
+    }
+
+    /// Start a new innermost frame.
+    pub fn push_frame(&mut self) {
+        self.frames.push(new_frame(HashMap::new()));
+    }
+
+    /// Start a new innermost frame for a lambda call. Until it is popped, assignments to
+    /// variables of the outer frames define new variables in it instead of changing them.
+    pub fn push_call_frame(&mut self) {
+        self.push_frame();
+        self.local = self.frames.len() - 1;
+    }
+
+    /// Discard the innermost frame. Its variables are dropped unless a lambda captured it.
+    pub fn pop_frame(&mut self) {
+        if self.frames.len() > 1 {
+            self.frames.pop();
+            self.local = self.local.min(self.frames.len() - 1);
+        }
+    }
+
+    /// A number that changes whenever a variable in one of the frames is assigned, declared or
+    /// removed, so a cached copy of a variable can be checked cheaply.
+    pub fn version(&self) -> u64 {
+        self.frames.iter()
+            .map(|f| f.version.load(Ordering::Relaxed))
+            .fold(self.frames.len() as u64, u64::wrapping_add)
+    }
+
+    /// The names of all visible variables, in no particular order.
+    pub fn keys(&self) -> Vec<String> {
+        self.iter().map(|(k, _)| k).collect()
+    }
+
+    /// All visible variables and their values, in no particular order.
+    pub fn iter(&self) -> std::vec::IntoIter<(String, Value)> {
+        let mut visible = HashMap::new();
+        for frame in &self.frames {
+            for (k, v) in frame.read().iter() {
+                visible.insert(k.to_owned(), frame.load(v.clone()));
+            }
+        }
+        visible.into_iter().collect::<Vec<_>>().into_iter()
+    }
+}
+
+impl IntoIterator for Context {
+    type Item = (String, Value);
+    type IntoIter = std::vec::IntoIter<(String, Value)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for &Context {
+    type Item = (String, Value);
+    type IntoIter = std::vec::IntoIter<(String, Value)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
@@ -0,0 +1,395 @@
+//! Format specifications for numbers and interpolated string literals, for example the `.3` in
+//! `f"{x:.3}"`, and the display format used when converting floats and complex numbers to
+//! strings.
+
+use crate::{Value, Context};
+use crate::value::r2f64;
+use crate::function::{EvalError, EvalErrorKind};
+use std::cell::RefCell;
+use std::sync::{Arc, RwLock};
+
+/// The display format of a context, shared by the contexts and lambdas that share its frames
+pub(crate) type DisplayCell = Arc<RwLock<FormatSpec>>;
+
+thread_local! {
+    /// The display format of the context being evaluated on this thread
+    static CURRENT: RefCell<Option<DisplayCell>> = const { RefCell::new(None) };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Align {
+    Left, Right, Center
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FormatType {
+    Default, Fixed, Exp, Eng, Sig, Hex, UpperHex, Octal, Binary
+}
+
+impl FormatType {
+    fn is_radix(self) -> bool {
+        matches!(self, Self::Hex | Self::UpperHex | Self::Octal | Self::Binary)
+    }
+}
+
+/// A format specification of the form `[[fill]align][+][0][width][,][.precision][type][p]`,
+/// where
+/// - `align` is `<` (left), `>` (right) or `^` (center). Numbers are right-aligned by default,
+///   everything else is left-aligned. `fill` is any character and defaults to a space.
+/// - `+` prints a sign for nonnegative real numbers as well
+/// - `0` pads real numbers with zeros after the sign, if no alignment is given
+/// - `width` is the minimum width in characters, at most [`MAX_WIDTH`]
+/// - `,` separates thousands in the integer part of decimal numbers
+/// - `precision` is the number of digits after the decimal point for numbers, converting real
+///   numbers to floats, or the maximum number of characters for strings, at most
+///   [`MAX_PRECISION`]
+/// - `type` is `f` (fixed point, 6 digits by default), `e` (scientific notation), `n`
+///   (engineering notation, with an exponent that is a multiple of 3), `g` (`precision`
+///   significant digits, 6 by default), or `x`/`X` (hexadecimal), `o` (octal), `b` (binary) for
+///   integers
+/// - `p` displays complex numbers in polar form, `r∠θ`
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormatSpec {
+    pub fill: char,
+    pub align: Option<Align>,
+    pub sign: bool,
+    pub zero: bool,
+    pub width: usize,
+    pub separator: bool,
+    pub precision: Option<usize>,
+    pub ty: FormatType,
+    pub polar: bool
+}
+
+impl Default for FormatSpec {
+    fn default() -> Self {
+        Self {
+            fill: ' ', align: None, sign: false, zero: false, width: 0,
+            separator: false, precision: None, ty: FormatType::Default, polar: false
+        }
+    }
+}
+
+/// The largest width allowed in a format specification
+pub const MAX_WIDTH: usize = u16::MAX as usize;
+/// The largest precision allowed in a format specification, the largest Rust's formatting
+/// accepts
+pub const MAX_PRECISION: usize = u16::MAX as usize;
+
+fn parse_align(c: char) -> Option<Align> {
+    match c {
+        '<' => Some(Align::Left),
+        '>' => Some(Align::Right),
+        '^' => Some(Align::Center),
+        _ => None
+    }
+}
+
+fn real(val: &Value) -> Option<f64> {
+    match val {
+        Value::Integer(n) => Some(*n as f64),
+        Value::Float(n) => Some(*n),
+        Value::Ratio(n) => Some(r2f64(n)),
+        _ => None
+    }
+}
+
+/// Inserts commas between groups of three digits in the leading integer part of a number
+// `is_multiple_of` would need Rust 1.87
+#[allow(clippy::manual_is_multiple_of)]
+fn group_thousands(s: &str) -> String {
+    let start = if s.starts_with('-') { 1 } else { 0 };
+    let end = s[start..].find(|c: char| !c.is_ascii_digit()).map_or(s.len(), |i| i + start);
+    let digits = &s[start..end];
+    let mut res = s[..start].to_owned();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            res.push(',');
+        }
+        res.push(c);
+    }
+    res + &s[end..]
+}
+
+/// Scientific notation with the exponent adjusted to a multiple of 3
+fn engineering(x: f64, precision: Option<usize>) -> String {
+    let sci = match precision {
+        Some(p) => format!("{:.*e}", p, x),
+        None => format!("{:e}", x)
+    };
+    let (mantissa, exp) = match sci.split_once('e') {
+        Some((m, e)) => (m, e.parse::<i32>().unwrap()),
+        None => return sci
+    };
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(m) => ("-", m),
+        None => ("", mantissa)
+    };
+    let shift = exp.rem_euclid(3) as usize;
+    let mut digits = mantissa.replace('.', "");
+    while digits.len() < shift + 1 {
+        digits.push('0');
+    }
+    let (int, frac) = digits.split_at(shift + 1);
+    if frac.is_empty() {
+        format!("{}{}e{}", sign, int, exp - shift as i32)
+    } else {
+        format!("{}{}.{}e{}", sign, int, frac, exp - shift as i32)
+    }
+}
+
+/// `digits` significant digits, in scientific notation if the exponent is very large or small
+fn significant(x: f64, digits: usize) -> String {
+    let digits = digits.max(1);
+    let sci = format!("{:.*e}", digits - 1, x);
+    match sci.split_once('e').and_then(|(_, e)| e.parse::<i32>().ok()) {
+        Some(e) if e >= -4 && e < digits as i32 && digits as i32 - 1 - e <= MAX_PRECISION as i32
+            => format!("{:.*}", (digits as i32 - 1 - e) as usize, x),
+        _ => sci
+    }
+}
+
+impl FormatSpec {
+    /// Parses a format specification, returning `None` if it is invalid or its width or
+    /// precision is above [`MAX_WIDTH`] or [`MAX_PRECISION`]. The empty string is the default specification, which formats values with
+    /// [`ToString`].
+    pub fn parse(s: &str) -> Option<Self> {
+        let chars: Vec<char> = s.chars().collect();
+        let mut spec = Self::default();
+        let mut i = 0;
+        if chars.len() >= 2 && parse_align(chars[1]).is_some() {
+            spec.fill = chars[0];
+            spec.align = parse_align(chars[1]);
+            i = 2;
+        } else if !chars.is_empty() && parse_align(chars[0]).is_some() {
+            spec.align = parse_align(chars[0]);
+            i = 1;
+        }
+        if chars.get(i) == Some(&'+') {
+            spec.sign = true;
+            i += 1;
+        }
+        if chars.get(i) == Some(&'0') {
+            spec.zero = true;
+            i += 1;
+        }
+        let digits = |i: &mut usize| {
+            let start = *i;
+            while chars.get(*i).is_some_and(char::is_ascii_digit) {
+                *i += 1;
+            }
+            chars[start..*i].iter().collect::<String>().parse::<usize>().ok()
+        };
+        if let Some(width) = digits(&mut i) {
+            if width > MAX_WIDTH {
+                return None
+            }
+            spec.width = width;
+        }
+        if chars.get(i) == Some(&',') {
+            spec.separator = true;
+            i += 1;
+        }
+        if chars.get(i) == Some(&'.') {
+            i += 1;
+            spec.precision = Some(digits(&mut i).filter(|&p| p <= MAX_PRECISION)?);
+        }
+        let ty = match chars.get(i) {
+            Some('f') => Some(FormatType::Fixed),
+            Some('e') => Some(FormatType::Exp),
+            Some('n') => Some(FormatType::Eng),
+            Some('g') => Some(FormatType::Sig),
+            Some('x') => Some(FormatType::Hex),
+            Some('X') => Some(FormatType::UpperHex),
+            Some('o') => Some(FormatType::Octal),
+            Some('b') => Some(FormatType::Binary),
+            _ => None
+        };
+        if let Some(ty) = ty {
+            spec.ty = ty;
+            i += 1;
+        }
+        if chars.get(i) == Some(&'p') {
+            spec.polar = true;
+            i += 1;
+        }
+        if i == chars.len() && !(spec.ty.is_radix() && spec.precision.is_some()) {
+            Some(spec)
+        } else {
+            None
+        }
+    }
+
+    /// Formats a value according to this specification. Floats and complex numbers use the
+    /// display format (see [`Context::set_display`]) unless a type, precision, or polar form is
+    /// given.
+    pub fn format(&self, val: &Value) -> Result<String, EvalError> {
+        let wrong_type = || -> EvalError { EvalErrorKind::WrongArgType(val.clone()).into() };
+        let is_real = real(val).is_some();
+        let spec = self.with_display();
+        let body = match val {
+            Value::Integer(n) if self.ty.is_radix() => {
+                let digits = match self.ty {
+                    FormatType::Hex => format!("{:x}", n.unsigned_abs()),
+                    FormatType::UpperHex => format!("{:X}", n.unsigned_abs()),
+                    FormatType::Octal => format!("{:o}", n.unsigned_abs()),
+                    _ => format!("{:b}", n.unsigned_abs())
+                };
+                spec.signed(if *n < 0 { format!("-{}", digits) } else { digits })
+            },
+            _ if self.ty.is_radix() => return Err(wrong_type()),
+            Value::Float(x) => spec.signed(spec.number(*x)),
+            Value::Complex(c) => spec.signed(spec.complex(c.re, c.im, |x| spec.number(x))),
+            Value::Integer(_) | Value::Ratio(_) if self.ty != FormatType::Default || self.precision.is_some()
+                => spec.signed(spec.number(real(val).unwrap())),
+            Value::Integer(n) if spec.separator => spec.signed(group_thousands(&n.to_string())),
+            Value::Integer(_) | Value::Ratio(_) => spec.signed(val.to_string()),
+            Value::Str(s) if self.ty == FormatType::Default => match self.precision {
+                Some(p) => s.chars().take(p).collect(),
+                None => s.clone()
+            },
+            _ if self.ty == FormatType::Default && self.precision.is_none() => val.to_string(),
+            _ => return Err(wrong_type())
+        };
+        let pad = self.width.saturating_sub(body.chars().count());
+        if pad == 0 {
+            return Ok(body)
+        }
+        crate::limits::check_len(self.width)?;
+        if self.zero && self.align.is_none() && is_real {
+            let split = if body.starts_with('+') || body.starts_with('-') { 1 } else { 0 };
+            return Ok(format!("{}{}{}", &body[..split], "0".repeat(pad), &body[split..]))
+        }
+        let fill = |n: usize| self.fill.to_string().repeat(n);
+        let align = self.align.unwrap_or(if is_real { Align::Right } else { Align::Left });
+        Ok(match align {
+            Align::Left => body + &fill(pad),
+            Align::Right => fill(pad) + &body,
+            Align::Center => fill(pad/2) + &body + &fill(pad - pad/2)
+        })
+    }
+
+    /// This specification with the notation of the display format, if it has none of its own
+    fn with_display(&self) -> Self {
+        if self.ty == FormatType::Default && self.precision.is_none() && !self.polar {
+            let display = current();
+            Self {
+                sign: self.sign || display.sign,
+                separator: self.separator || display.separator,
+                precision: display.precision,
+                ty: display.ty,
+                polar: display.polar,
+                ..self.clone()
+            }
+        } else {
+            self.clone()
+        }
+    }
+
+    /// Formats a float without its sign option or padding
+    fn number(&self, x: f64) -> String {
+        let s = match (self.ty, self.precision) {
+            (FormatType::Default, None) => x.to_string(),
+            (FormatType::Default, Some(p)) | (FormatType::Fixed, Some(p)) => format!("{:.*}", p, x),
+            (FormatType::Fixed, None) => format!("{:.6}", x),
+            (FormatType::Exp, Some(p)) => format!("{:.*e}", p, x),
+            (FormatType::Exp, None) => format!("{:e}", x),
+            (FormatType::Eng, p) => engineering(x, p),
+            (FormatType::Sig, p) => significant(x, p.unwrap_or(6)),
+            _ => x.to_string()
+        };
+        if self.separator {
+            group_thousands(&s)
+        } else {
+            s
+        }
+    }
+
+    fn complex<F: Fn(f64) -> String>(&self, re: f64, im: f64, part: F) -> String {
+        if self.polar {
+            format!("{}∠{}", part(re.hypot(im)), part(im.atan2(re)))
+        } else if im.is_sign_negative() && !im.is_nan() {
+            format!("{} - {}i", part(re), part(-im))
+        } else {
+            format!("{} + {}i", part(re), part(im))
+        }
+    }
+
+    fn signed(&self, body: String) -> String {
+        if self.sign && !body.starts_with('-') {
+            format!("+{}", body)
+        } else {
+            body
+        }
+    }
+}
+
+/// Makes the display format of a context current on this thread until it is dropped
+pub(crate) struct DisplayGuard(Option<Option<DisplayCell>>);
+
+impl Drop for DisplayGuard {
+    fn drop(&mut self) {
+        if let Some(prev) = self.0.take() {
+            CURRENT.with(|c| *c.borrow_mut() = prev);
+        }
+    }
+}
+
+/// Make `cell` the current display format, used while evaluating in the context it belongs to
+pub(crate) fn enter(cell: &DisplayCell) -> DisplayGuard {
+    CURRENT.with(|c| {
+        let mut c = c.borrow_mut();
+        match &*c {
+            Some(current) if Arc::ptr_eq(current, cell) => DisplayGuard(None),
+            _ => DisplayGuard(Some(c.replace(cell.clone())))
+        }
+    })
+}
+
+/// Change the display format of the context being evaluated, for the `set_display` function.
+/// Fails if no context is being evaluated.
+pub(crate) fn set_current(spec: FormatSpec) -> Result<(), EvalError> {
+    CURRENT.with(|c| match &*c.borrow() {
+        Some(cell) => {
+            *cell.write().unwrap() = spec;
+            Ok(())
+        },
+        None => Err(EvalErrorKind::Other("No context to set the display format of".to_owned()).into())
+    })
+}
+
+/// The display format of the context being evaluated, or the default outside of evaluation
+fn current() -> FormatSpec {
+    CURRENT.with(|c| match &*c.borrow() {
+        Some(cell) => cell.read().unwrap().clone(),
+        None => FormatSpec::default()
+    })
+}
+
+/// Run `f` with the display format of `ctx`, for example to print the result of evaluating an
+/// expression in it. Values converted to strings outside of evaluation use the default format.
+pub fn with_display<T>(ctx: &Context, f: impl FnOnce() -> T) -> T {
+    let _display = enter(ctx.display_cell());
+    f()
+}
+
+/// Converts a float to a string using the current display format. If `debug` is true and the display
+/// format is the default, uses the [`Debug`](std::fmt::Debug) implementation of `f64` instead.
+pub fn display_float(x: f64, debug: bool) -> String {
+    let spec = current();
+    if debug && spec == FormatSpec::default() {
+        format!("{:?}", x)
+    } else {
+        spec.signed(spec.number(x))
+    }
+}
+
+/// Converts a complex number to a string using the display format, see [`display_float`].
+pub fn display_complex(re: f64, im: f64, debug: bool) -> String {
+    let spec = current();
+    if debug && spec == FormatSpec::default() {
+        spec.complex(re, im, |x| format!("{:?}", x))
+    } else {
+        spec.signed(spec.complex(re, im, |x| spec.number(x)))
+    }
+}
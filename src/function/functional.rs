@@ -0,0 +1,257 @@
+//! Functions that create new functions from existing ones. The results are builtin functions
+//! that hold on to their arguments, so they can be used anywhere a lambda could.
+
+use crate::function::*;
+use crate::Value;
+use std::collections::HashMap;
+use std::sync::{Mutex, Weak};
+
+use crate::Context;
+lazy_static::lazy_static! {
+    /// A `lazy_static` [`Context`] containing all the definitions from [`functional`]
+    pub static ref CTX_ALL: Context = {
+        use crate::InsertFunction;
+        let mut ctx = Context::new();
+        ctx.insert_function("compose".to_owned(), &compose);
+        ctx.insert_function("partial".to_owned(), &partial);
+        ctx.insert_function("flip".to_owned(), &flip);
+        ctx.insert_function("curry".to_owned(), &curry);
+        ctx.insert_function("memoize".to_owned(), &memoize);
+        ctx.insert_function("cache_clear".to_owned(), &cache_clear);
+        ctx
+    };
+    /// The caches of the functions returned by [`memoize`], by the address of the function
+    static ref MEMO_CACHES: Mutex<HashMap<usize, Weak<Mutex<MemoCache>>>> = Mutex::new(HashMap::new());
+}
+
+fn callable_arg(val: &Value) -> std::result::Result<Value, EvalError> {
+    if val.is_callable() {
+        Ok(val.clone())
+    } else {
+        Err(EvalErrorKind::WrongArgType(val.clone()).into())
+    }
+}
+
+fn new_function(f: impl Fn(Vec<Value>) -> Result + Send + Sync + 'static) -> Value {
+    Value::Function(Function(Arc::new(f)))
+}
+
+/// Returns a function that calls `f` and then `g` on the result, as used by the `>>` operator.
+/// Fails if either value is not a function or lambda.
+pub fn then(f: Value, g: Value) -> Result {
+    if !f.is_function() || !g.is_function() {
+        return Err(EvalErrorKind::WrongOpArgTypes(f, g).into())
+    }
+    Ok(new_function(move |args| g.eval(vec![f.eval(args)?])))
+}
+
+/// Composes functions right to left: `compose(f, g, h)(x)` is `f(g(h(x)))`. The last function
+/// may take any number of arguments, the others take one.
+pub fn compose(args: Vec<Value>) -> Result {
+    min_args(args.len(), 1)?;
+    let funcs = args.iter().map(callable_arg).collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(new_function(move |args| {
+        let (last, rest) = funcs.split_last().unwrap();
+        let mut res = last.eval(args)?;
+        for f in rest.iter().rev() {
+            res = f.eval(vec![res])?;
+        }
+        Ok(res)
+    }))
+}
+
+/// Fixes the first arguments of a function: `partial(f, a, b)(c)` is `f(a, b, c)`.
+pub fn partial(args: Vec<Value>) -> Result {
+    min_args(args.len(), 1)?;
+    let func = callable_arg(&args[0])?;
+    let fixed = args[1..].to_vec();
+    Ok(new_function(move |args| {
+        let mut all = fixed.clone();
+        all.extend(args);
+        func.eval(all)
+    }))
+}
+
+/// Swaps the first two arguments of a function: `flip(f)(a, b, c)` is `f(b, a, c)`.
+pub fn flip(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    let func = callable_arg(&args[0])?;
+    Ok(new_function(move |mut args| {
+        min_args(args.len(), 2)?;
+        args.swap(0, 1);
+        func.eval(args)
+    }))
+}
+
+/// Returns a function that collects arguments over several calls and calls `f` once it has
+/// `n` of them: `curry(f, 3)(a)(b)(c)` and `curry(f, 3)(a, b)(c)` are `f(a, b, c)`. `n` can be
+/// left out for lambdas, and is then the number of parameters without a default value.
+pub fn curry(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 2)?;
+    let func = callable_arg(&args[0])?;
+    let count = match (args.get(1), &func) {
+        (Some(Value::Integer(n)), _) if *n >= 0 => *n as usize,
+        (Some(x), _) => return Err(EvalErrorKind::WrongArgValue(x.clone()).into()),
+        (None, Value::Lambda{args: params, ..}) => crate::tree::Pattern::arity(params).0,
+        (None, x) => return Err(EvalErrorKind::WrongArgType(x.clone()).into())
+    };
+    curry_inner(func, count, vec![])
+}
+
+fn curry_inner(func: Value, count: usize, collected: Vec<Value>) -> Result {
+    if collected.len() >= count {
+        return func.eval(collected)
+    }
+    Ok(new_function(move |args| {
+        let mut all = collected.clone();
+        all.extend(args);
+        curry_inner(func.clone(), count, all)
+    }))
+}
+
+/// The arguments of a call, as a cache key. Only arguments that are [`Value::is_hashable`] are
+/// used as keys. Unlike with `==`, arguments are only equal if they have the same types and
+/// values, so `1` and `1.0` have separate entries, and floats are compared by their bits, so
+/// `0.0` and `-0.0` do as well.
+struct MemoKey(Vec<Value>);
+
+/// The bits of a float, with every NaN the same
+fn float_bits(x: f64) -> u64 {
+    if x.is_nan() { f64::NAN.to_bits() } else { x.to_bits() }
+}
+
+fn hash_exact<H: std::hash::Hasher>(v: &Value, state: &mut H) {
+    use std::hash::Hash;
+    std::mem::discriminant(v).hash(state);
+    match v {
+        Value::Integer(n) => n.hash(state),
+        Value::Ratio(r) => r.hash(state),
+        Value::Float(x) => float_bits(*x).hash(state),
+        Value::Complex(c) => { float_bits(c.re).hash(state); float_bits(c.im).hash(state) },
+        Value::List(l) => { l.len().hash(state); l.iter().for_each(|v| hash_exact(v, state)) },
+        Value::Bool(b) => b.hash(state),
+        Value::Str(s) => s.hash(state),
+        _ => ()
+    }
+}
+
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Integer(a), Value::Integer(b)) => a == b,
+        (Value::Ratio(a), Value::Ratio(b)) => a == b,
+        (Value::Float(a), Value::Float(b)) => float_bits(*a) == float_bits(*b),
+        (Value::Complex(a), Value::Complex(b))
+            => float_bits(a.re) == float_bits(b.re) && float_bits(a.im) == float_bits(b.im),
+        (Value::List(a), Value::List(b))
+            => a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b)),
+        (Value::Bool(a), Value::Bool(b)) => a == b,
+        (Value::Str(a), Value::Str(b)) => a == b,
+        (Value::Void, Value::Void) => true,
+        _ => false
+    }
+}
+
+impl std::hash::Hash for MemoKey {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.len().hash(state);
+        self.0.iter().for_each(|v| hash_exact(v, state))
+    }
+}
+
+impl PartialEq for MemoKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len() && self.0.iter().zip(&other.0).all(|(a, b)| same(a, b))
+    }
+}
+
+impl Eq for MemoKey {}
+
+struct MemoCache {
+    entries: HashMap<MemoKey, (Value, u64)>,
+    capacity: Option<usize>,
+    /// Incremented on each use of the cache, to find the least recently used entry
+    clock: u64
+}
+
+impl MemoCache {
+    fn get(&mut self, key: &MemoKey) -> Option<Value> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.entries.get_mut(key).map(|(v, used)| {
+            *used = clock;
+            v.clone()
+        })
+    }
+
+    fn insert(&mut self, key: MemoKey, value: Value) {
+        if self.capacity == Some(0) {
+            return
+        }
+        if self.capacity.is_some_and(|c| self.entries.len() >= c) {
+            let oldest = self.entries.iter().min_by_key(|(_, (_, used))| *used).map(|(k, _)| k);
+            if let Some(oldest) = oldest.map(|k| MemoKey(k.0.clone())) {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.entries.insert(key, (value, self.clock));
+    }
+}
+
+fn function_addr(f: &Function) -> usize {
+    Arc::as_ptr(&f.0) as *const () as usize
+}
+
+/// Returns a function that calls `f` and remembers the result for each list of arguments, so
+/// calling it again with the same arguments returns the remembered result without calling `f`.
+/// Arguments are the same only if they also have the same types, so `1` and `1.0` are cached
+/// separately. With a capacity `n`, only the results of the `n` most recently used argument lists are
+/// kept. Calls with arguments that cannot be compared reliably, such as functions or NaN, are
+/// not cached. The cache can be emptied with [`cache_clear`].
+pub fn memoize(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 2)?;
+    let func = callable_arg(&args[0])?;
+    let capacity = match args.get(1) {
+        None => None,
+        Some(Value::Integer(n)) if *n >= 0 => Some(*n as usize),
+        Some(x) => return Err(EvalErrorKind::WrongArgValue(x.clone()).into())
+    };
+    let cache = Arc::new(Mutex::new(MemoCache{entries: HashMap::new(), capacity, clock: 0}));
+    let weak = Arc::downgrade(&cache);
+    let memoized = Function(Arc::new(move |args: Vec<Value>| {
+        if !args.iter().all(|a| a.is_hashable()) {
+            return func.eval(args)
+        }
+        let key = MemoKey(args);
+        if let Some(v) = cache.lock().unwrap().get(&key) {
+            return Ok(v)
+        }
+        // the lock is not held during the call, which may call this function again
+        let res = func.eval(key.0.clone())?;
+        cache.lock().unwrap().insert(key, res.clone());
+        Ok(res)
+    }));
+    let mut caches = MEMO_CACHES.lock().unwrap();
+    caches.retain(|_, c| c.strong_count() > 0);
+    caches.insert(function_addr(&memoized), weak);
+    Ok(Value::Function(memoized))
+}
+
+/// Empties the cache of a function returned by [`memoize`] and returns the number of results
+/// that were removed. Fails for other functions.
+pub fn cache_clear(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    let cache = match &args[0] {
+        Value::Function(f) => MEMO_CACHES.lock().unwrap().get(&function_addr(f)).and_then(|c| c.upgrade()),
+        _ => None
+    };
+    match cache {
+        Some(cache) => {
+            let mut cache = cache.lock().unwrap();
+            let count = cache.entries.len();
+            cache.entries.clear();
+            Ok(Value::Integer(count as i64))
+        },
+        None => Err(EvalErrorKind::WrongArgType(args[0].clone()).into())
+    }
+}
@@ -0,0 +1,239 @@
+//! Interpolation and curve fitting. Functions that construct an interpolant ([`spline`] and
+//! [`lagrange`]) return a callable [`Value::Function`] of one argument.
+
+use crate::function::*;
+use crate::Value;
+use crate::value::r2f64;
+use std::sync::Arc;
+
+use crate::Context;
+lazy_static::lazy_static! {
+    /// A `lazy_static` [`Context`] containing all the definitions from [`interp`]
+    pub static ref CTX_ALL: Context = {
+        use crate::InsertFunction;
+        let mut ctx = Context::new();
+        ctx.insert_function("lerp".to_owned(), &lerp);
+        ctx.insert_function("interp_linear".to_owned(), &interp_linear);
+        ctx.insert_function("spline".to_owned(), &spline);
+        ctx.insert_function("lagrange".to_owned(), &lagrange);
+        ctx.insert_function("polyfit".to_owned(), &polyfit);
+        ctx.insert_function("polyval".to_owned(), &polyval);
+        ctx
+    };
+}
+
+/// Linearly interpolates between two values, `lerp(a, b, t) = a + (b - a)*t`.
+/// Requires exactly three numerical arguments, returns a number.
+pub fn lerp(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 3, 3)?;
+    let diff = (args[1].clone() - args[0].clone())?;
+    args[0].clone() + (diff * args[2].clone())?
+}
+
+/// Piecewise linear interpolation of the points `(xs[i], ys[i])` at `x`. Values of `x` outside
+/// the range of `xs` are clamped to the first or last point.
+/// Requires two lists of real numbers of the same length, with `xs` strictly increasing, and one
+/// real number. Always returns a [`Value::Float`].
+pub fn interp_linear(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 3, 3)?;
+    let (xs, ys) = points(&args[0], &args[1], 1)?;
+    let x = float_arg(&args[2])?;
+    let n = xs.len();
+    if x <= xs[0] {
+        return Ok(Value::Float(ys[0]))
+    } else if x >= xs[n-1] {
+        return Ok(Value::Float(ys[n-1]))
+    }
+    let i = segment(&xs, x);
+    let t = (x - xs[i]) / (xs[i+1] - xs[i]);
+    Ok(Value::Float(ys[i] + (ys[i+1] - ys[i])*t))
+}
+
+/// Constructs a natural cubic spline through the points `(xs[i], ys[i])`. Outside the range of
+/// `xs` the first or last cubic segment is extrapolated.
+/// Requires two lists of real numbers of the same length (at least two), with `xs` strictly
+/// increasing. Returns a function of one real argument that returns a [`Value::Float`].
+pub fn spline(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let (xs, ys) = points(&args[0], &args[1], 2)?;
+    let n = xs.len();
+    let h: Vec<f64> = xs.windows(2).map(|w| w[1] - w[0]).collect();
+    // second derivatives at each point, zero at both ends (natural boundary conditions),
+    // found by solving a tridiagonal system with the Thomas algorithm
+    let mut m = vec![0.0; n];
+    if n > 2 {
+        let mut diag = vec![0.0; n];
+        let mut rhs = vec![0.0; n];
+        for i in 1..n-1 {
+            diag[i] = 2.0*(h[i-1] + h[i]);
+            rhs[i] = 6.0*((ys[i+1] - ys[i])/h[i] - (ys[i] - ys[i-1])/h[i-1]);
+        }
+        for i in 2..n-1 {
+            let w = h[i-1]/diag[i-1];
+            diag[i] -= w*h[i-1];
+            rhs[i] -= w*rhs[i-1];
+        }
+        for i in (1..n-1).rev() {
+            m[i] = (rhs[i] - h[i]*m[i+1])/diag[i];
+        }
+    }
+    let func = move |args: Vec<Value>| -> Result {
+        bound_args(args.len(), 1, 1)?;
+        let x = float_arg(&args[0])?;
+        let i = segment(&xs, x);
+        let (a, b) = (xs[i+1] - x, x - xs[i]);
+        let res = m[i]*a*a*a/(6.0*h[i]) + m[i+1]*b*b*b/(6.0*h[i])
+            + (ys[i]/h[i] - m[i]*h[i]/6.0)*a
+            + (ys[i+1]/h[i] - m[i+1]*h[i]/6.0)*b;
+        Ok(Value::Float(res))
+    };
+    Ok(Value::Function(Function(Arc::new(func))))
+}
+
+/// Constructs the Lagrange polynomial through the points `(xs[i], ys[i])`, evaluated in
+/// barycentric form.
+/// Requires two lists of real numbers of the same length (at least one), with all `xs` distinct.
+/// Returns a function of one real argument that returns a [`Value::Float`].
+pub fn lagrange(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let (xs, ys) = (float_list(&args[0])?, float_list(&args[1])?);
+    if xs.is_empty() || xs.len() != ys.len() {
+        return Err(EvalErrorKind::WrongArgValue(args[1].clone()).into())
+    }
+    let mut weights = vec![1.0; xs.len()];
+    for (j, w) in weights.iter_mut().enumerate() {
+        for (k, xk) in xs.iter().enumerate() {
+            if k != j {
+                *w /= xs[j] - xk;
+            }
+        }
+    }
+    if weights.iter().any(|w| !w.is_finite()) {
+        return Err(EvalErrorKind::WrongArgValue(args[0].clone()).into())
+    }
+    let func = move |args: Vec<Value>| -> Result {
+        bound_args(args.len(), 1, 1)?;
+        let x = float_arg(&args[0])?;
+        let (mut numer, mut denom) = (0.0, 0.0);
+        for ((xj, yj), wj) in xs.iter().zip(&ys).zip(&weights) {
+            if x == *xj {
+                return Ok(Value::Float(*yj))
+            }
+            let t = wj/(x - xj);
+            numer += t*yj;
+            denom += t;
+        }
+        Ok(Value::Float(numer/denom))
+    };
+    Ok(Value::Function(Function(Arc::new(func))))
+}
+
+/// Fits a polynomial of the given degree to the points `(xs[i], ys[i])` by least squares.
+/// The coefficients are returned lowest degree first, so they can be passed directly to
+/// [`polyval`].
+/// Requires two lists of real numbers of the same length and a nonnegative integer less than
+/// the number of points, always returns a list of [`Value::Float`].
+pub fn polyfit(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 3, 3)?;
+    let (xs, ys) = (float_list(&args[0])?, float_list(&args[1])?);
+    if xs.len() != ys.len() {
+        return Err(EvalErrorKind::WrongArgValue(args[1].clone()).into())
+    }
+    let deg = match args[2] {
+        Value::Integer(n) if n >= 0 && (n as usize) < xs.len() => n as usize,
+        Value::Integer(_) => return Err(EvalErrorKind::WrongArgValue(args[2].clone()).into()),
+        _ => return Err(EvalErrorKind::WrongArgType(args[2].clone()).into())
+    };
+    let vandermonde = (0..=deg)
+        .map(|p| xs.iter().map(|x| x.powi(p as i32)).collect())
+        .collect();
+    match least_squares(vandermonde, ys) {
+        Some(coeffs) => Ok(Value::List(coeffs.into_iter().map(Value::Float).collect())),
+        None => Err(EvalErrorKind::WrongArgValue(args[0].clone()).into())
+    }
+}
+
+/// Evaluates a polynomial with the given coefficients (lowest degree first) at a point using
+/// Horner's method.
+/// Requires a list of numbers and one number, returns a number.
+pub fn polyval(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    if let Value::List(coeffs) = &args[0] {
+        let mut res = Value::Integer(0);
+        for c in coeffs.iter().rev() {
+            res = ((res * args[1].clone())? + c.clone())?;
+        }
+        Ok(res)
+    } else {
+        Err(EvalErrorKind::WrongArgType(args[0].clone()).into())
+    }
+}
+
+fn float_arg(val: &Value) -> std::result::Result<f64, EvalError> {
+    match val {
+        Value::Integer(n) => Ok(*n as f64),
+        Value::Float(n) => Ok(*n),
+        Value::Ratio(n) => Ok(r2f64(n)),
+        _ => Err(EvalErrorKind::WrongArgType(val.clone()).into())
+    }
+}
+
+fn float_list(val: &Value) -> std::result::Result<Vec<f64>, EvalError> {
+    list_arg(val)?.iter().map(float_arg).collect()
+}
+
+/// Converts a pair of lists to floats, checking that they have the same length (at least
+/// `min_len`) and that `xs` is strictly increasing
+fn points(xs: &Value, ys: &Value, min_len: usize) -> std::result::Result<(Vec<f64>, Vec<f64>), EvalError> {
+    let (xl, yl) = (float_list(xs)?, float_list(ys)?);
+    if xl.len() != yl.len() || xl.len() < min_len {
+        return Err(EvalErrorKind::WrongArgValue(ys.clone()).into())
+    }
+    if xl.windows(2).any(|w| w[0] >= w[1]) {
+        return Err(EvalErrorKind::WrongArgValue(xs.clone()).into())
+    }
+    Ok((xl, yl))
+}
+
+/// Index `i` of the segment `xs[i]..xs[i+1]` containing `x`, clamped to the first and last
+/// segments
+fn segment(xs: &[f64], x: f64) -> usize {
+    let i = xs.partition_point(|v| *v <= x);
+    i.saturating_sub(1).min(xs.len() - 2)
+}
+
+/// Solves the least squares problem `min |a*x - b|` using Householder QR decomposition, where
+/// `a` is given as a list of columns. Returns `None` if `a` does not have full column rank.
+fn least_squares(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let rows = b.len();
+    let cols = a.len();
+    for k in 0..cols {
+        let norm = a[k][k..].iter().map(|x| x*x).sum::<f64>().sqrt();
+        if norm == 0.0 {
+            return None
+        }
+        let alpha = if a[k][k] > 0.0 { -norm } else { norm };
+        let mut v = a[k][k..].to_vec();
+        v[0] -= alpha;
+        let vnorm_sq: f64 = v.iter().map(|x| x*x).sum();
+        if vnorm_sq == 0.0 {
+            continue
+        }
+        for col in a.iter_mut().skip(k).chain(std::iter::once(&mut b)) {
+            let dot: f64 = v.iter().zip(&col[k..]).map(|(x, y)| x*y).sum();
+            for (c, x) in col[k..rows].iter_mut().zip(&v) {
+                *c -= 2.0*dot/vnorm_sq*x;
+            }
+        }
+    }
+    let tol = 1e-12 * (0..cols).map(|k| a[k][k].abs()).fold(0.0, f64::max);
+    let mut x = vec![0.0; cols];
+    for k in (0..cols).rev() {
+        if a[k][k].abs() <= tol {
+            return None
+        }
+        let sum: f64 = ((k+1)..cols).map(|j| a[j][k]*x[j]).sum();
+        x[k] = (b[k] - sum)/a[k][k];
+    }
+    Some(x)
+}
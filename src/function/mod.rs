@@ -4,6 +4,13 @@ pub mod num;
 pub mod types;
 pub mod util;
 pub mod complex;
+pub mod signal;
+pub mod interp;
+pub mod optimize;
+pub mod string;
+pub mod re;
+pub mod functional;
+pub mod refs;
 #[cfg(feature = "random")]
 pub mod random;
 pub mod io;
@@ -16,9 +23,11 @@ pub enum EvalErrorKind {
     TooFewArgs{min: usize, count: usize}, TooManyArgs{max: usize, count: usize}, 
     AssignLeftNotIdent(Value), WrongFunc(Value),
     IdentifierReserved(String), InvalidSpecialIdent(String),
-    VariableUnset(String),
+    VariableUnset(String), PatternMismatch(Value), NamedArg(String), DuplicateArg(String),
     WrongArgType(Value), WrongOpArgTypes(Value, Value), WrongArgValue(Value), ListOutOfBounds(i64),
     IOError(Arc<std::io::Error>),
+    NoConvergence(usize),
+    LimitExceeded(crate::limits::Limit),
     Other(String)
 }
 
@@ -71,6 +80,12 @@ impl std::fmt::Display for EvalError {
                 => write!(f, "'{}' is not a function", val),
             EvalErrorKind::VariableUnset(s)
                 => write!(f, "Variable '{}' has not been initialized", s),
+            EvalErrorKind::PatternMismatch(val)
+                => write!(f, "Value '{}' does not match the pattern", val),
+            EvalErrorKind::NamedArg(name)
+                => write!(f, "Unexpected named argument '{}'", name),
+            EvalErrorKind::DuplicateArg(name)
+                => write!(f, "Argument '{}' given more than once", name),
             EvalErrorKind::WrongArgType(a)
                 => write!(f, "Argument '{}' is of the wrong type", a),
             EvalErrorKind::WrongOpArgTypes(a, b)
@@ -81,6 +96,10 @@ impl std::fmt::Display for EvalError {
                 => write!(f, "List index {} out of bounds", i),
             EvalErrorKind::IOError(e)
                 => write!(f, "IO Error: {:?}", e),
+            EvalErrorKind::NoConvergence(n)
+                => write!(f, "Failed to converge after {} iterations", n),
+            EvalErrorKind::LimitExceeded(limit)
+                => write!(f, "Evaluation limit exceeded: {}", limit),
             EvalErrorKind::Other(s)
                 => write!(f, "{}", s)
         }
@@ -134,6 +153,36 @@ pub fn bound_args(count: usize, min: usize, max: usize) -> std::result::Result<(
     max_args(count, max)
 }
 
+/// Borrows a list, or collects a finite [`Value::Iter`] sequence into a list.
+pub fn list_arg(val: &Value) -> std::result::Result<std::borrow::Cow<'_, [Value]>, EvalError> {
+    use std::borrow::Cow;
+    match val {
+        Value::List(l) => Ok(Cow::Borrowed(l)),
+        Value::Iter(seq) => Ok(Cow::Owned(seq.collect()?)),
+        _ => Err(EvalErrorKind::WrongArgType(val.clone()).into())
+    }
+}
+
+/// Iterates over the elements of a list or a [`Value::Iter`] sequence without collecting the
+/// sequence. The iterator may never end, so callers should stop early. Each element of a
+/// sequence counts as a step towards the [`crate::limits`].
+pub fn iter_arg(val: &Value) -> std::result::Result<crate::lazy::LazyIter<'_>, EvalError> {
+    match val {
+        Value::List(l) => Ok(Box::new(l.iter().cloned().map(Ok))),
+        Value::Iter(seq) => Ok(Box::new(seq.iter().map(|x| crate::limits::step().and(x)))),
+        _ => Err(EvalErrorKind::WrongArgType(val.clone()).into())
+    }
+}
+
+/// Like [`iter_arg`], but fails for sequences known to be infinite.
+pub fn finite_iter_arg(val: &Value) -> std::result::Result<crate::lazy::LazyIter<'_>, EvalError> {
+    match val {
+        Value::Iter(seq) if seq.is_infinite()
+            => Err(EvalErrorKind::Other("Cannot collect an infinite sequence".to_owned()).into()),
+        _ => iter_arg(val)
+    }
+}
+
 pub fn to_float(val: Value) -> Result {
     use Value::*;
     match val {
@@ -29,6 +29,11 @@ lazy_static::lazy_static! {
         ctx.insert_function("solve".to_owned(), &solve);
         ctx.insert_function("gamma".to_owned(), &gamma);
         ctx.insert_function("lambert_w".to_owned(), &lambert_w);
+        ctx.insert_function("popcount".to_owned(), &popcount);
+        ctx.insert_function("leading_zeros".to_owned(), &leading_zeros);
+        ctx.insert_function("trailing_zeros".to_owned(), &trailing_zeros);
+        ctx.insert_function("bit_get".to_owned(), &bit_get);
+        ctx.insert_function("bit_set".to_owned(), &bit_set);
         ctx.insert("pi".to_owned(), PI.clone());
         ctx.insert("e".to_owned(), E.clone());
         ctx.insert("inf".to_owned(), INF.clone());
@@ -49,10 +54,21 @@ pub const NEG_INF: Value = Value::Float(std::f64::NEG_INFINITY);
 /// The [`Value`] form of [`std::f64::NAN`]
 pub const NAN: Value = Value::Float(std::f64::NAN);
 
+/// The values compared by [`min`] and [`max`]: the elements of a single list or sequence
+/// argument, or else the arguments themselves
+fn compared_args(args: Vec<Value>) -> std::result::Result<Vec<Value>, EvalError> {
+    match args.as_slice() {
+        [Value::List(_) | Value::Iter(_)] => Ok(list_arg(&args[0])?.into_owned()),
+        _ => Ok(args)
+    }
+}
+
 /// Calculates the minimum of a series of arguments. This is done by comparing each argument
 /// successively to the minimum, and updating the maximum if the argument is strictly smaller.
+/// A single list or sequence argument is replaced by its elements.
 /// Requires at least one argument of any type, always returns one of the arguments.
 pub fn min(args: Vec<Value>) -> Result {
+    let args = compared_args(args)?;
     min_args(args.len(), 1)?;
     let mut lowest = &args[0];
     for arg in &args[1..] {
@@ -65,8 +81,10 @@ pub fn min(args: Vec<Value>) -> Result {
 
 /// Calculates the maximum of a series of arguments. This is done by comparing each argument
 /// successively to the maximum, and updating the maximum if the argument is strictly greater.
+/// A single list or sequence argument is replaced by its elements.
 /// Requires at least one argument of any type, always returns one of the arguments.
 pub fn max(args: Vec<Value>) -> Result {
+    let args = compared_args(args)?;
     min_args(args.len(), 1)?;
     let mut highest = &args[0];
     for arg in &args[1..] {
@@ -310,6 +328,7 @@ pub fn factors(args: Vec<Value>) -> Result {
         let mut res: Vec<i64> = std::iter::repeat(2).take(fac2 as usize).collect();
         let mut f = 3;
         while n > 1 {
+            crate::limits::step()?;
             if n % f == 0 {
                 res.push(f);
                 n /= f;
@@ -452,3 +471,52 @@ pub fn solve(args: Vec<Value>) -> Result {
     Ok(res)
 }
 
+fn int_arg(val: &Value) -> std::result::Result<i64, EvalError> {
+    match val {
+        Value::Integer(n) => Ok(*n),
+        _ => Err(EvalErrorKind::WrongArgType(val.clone()).into())
+    }
+}
+
+fn bit_index(val: &Value) -> std::result::Result<u32, EvalError> {
+    match int_arg(val)? {
+        n @ 0..=63 => Ok(n as u32),
+        _ => Err(EvalErrorKind::WrongArgValue(val.clone()).into())
+    }
+}
+
+/// Counts the bits that are set in the 64-bit two's complement representation of an integer.
+pub fn popcount(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    Ok(Value::Integer(int_arg(&args[0])?.count_ones() as i64))
+}
+
+/// Counts the zero bits above the highest set bit of an integer, out of 64.
+pub fn leading_zeros(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    Ok(Value::Integer(int_arg(&args[0])?.leading_zeros() as i64))
+}
+
+/// Counts the zero bits below the lowest set bit of an integer, or 64 for zero.
+pub fn trailing_zeros(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    Ok(Value::Integer(int_arg(&args[0])?.trailing_zeros() as i64))
+}
+
+/// Checks whether bit `i` (0 to 63, from the least significant) of an integer is set.
+pub fn bit_get(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let (n, i) = (int_arg(&args[0])?, bit_index(&args[1])?);
+    Ok(Value::Bool(n & (1 << i) != 0))
+}
+
+/// Sets bit `i` of an integer to the third argument, `true` by default.
+pub fn bit_set(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 3)?;
+    let (n, i) = (int_arg(&args[0])?, bit_index(&args[1])?);
+    match args.get(2).unwrap_or(&Value::Bool(true)) {
+        Value::Bool(true) => Ok(Value::Integer(n | (1 << i))),
+        Value::Bool(false) => Ok(Value::Integer(n & !(1 << i))),
+        x => Err(EvalErrorKind::WrongArgType(x.clone()).into())
+    }
+}
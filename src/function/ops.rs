@@ -12,6 +12,7 @@ lazy_static::lazy_static! {
         ctx.insert_function("div".to_owned(), &div);
         ctx.insert_function("frac".to_owned(), &frac);
         ctx.insert_function("mod".to_owned(), &modulo);
+        ctx.insert_function("div_floor".to_owned(), &div_floor);
         ctx.insert_function("pow".to_owned(), &pow);
         ctx.insert_function("cmp".to_owned(), &cmp);
         ctx
@@ -90,6 +91,11 @@ pub fn modulo(args: Vec<Value>) -> Result {
     }
 }
 
+pub fn div_floor(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    args[0].clone().div_floor(args[1].clone())
+}
+
 pub fn pow(args: Vec<Value>) -> Result {
     if args.len() == 0 {
         Ok(Value::Integer(1))
@@ -0,0 +1,319 @@
+//! Numerical minimization and maximization of real-valued functions. All functions return a
+//! list `(argmin, value)`, where `argmin` is a number if the initial guess was a number or a list
+//! if the guess was a list of numbers (in which case the function is called with one argument per
+//! element). If the method fails to converge an [`EvalErrorKind::NoConvergence`] is returned.
+
+use crate::function::*;
+use crate::Value;
+
+use crate::Context;
+lazy_static::lazy_static! {
+    /// A `lazy_static` [`Context`] containing all the definitions from [`optimize`]
+    pub static ref CTX_ALL: Context = {
+        use crate::InsertFunction;
+        let mut ctx = Context::new();
+        ctx.insert_function("minimize".to_owned(), &minimize);
+        ctx.insert_function("maximize".to_owned(), &maximize);
+        ctx.insert_function("minimize_bfgs".to_owned(), &minimize_bfgs);
+        ctx.insert_function("maximize_bfgs".to_owned(), &maximize_bfgs);
+        ctx
+    };
+}
+
+const MAX_ITER: usize = 1000;
+/// Relative tolerance for the bracketed 1-D search, about the square root of machine epsilon
+const BRENT_TOL: f64 = 1.5e-8;
+const GOLDEN: f64 = 0.381_966_011_250_105;
+
+/// minimize(f, guess) uses the Nelder-Mead simplex method starting from `guess`.
+/// minimize(f, a, b) searches for a minimum of a function of one variable in the interval
+/// `[a, b]` using Brent's method.
+/// Requires a function and either one number or list of numbers, or two real numbers. Always
+/// returns a list `(argmin, value)`.
+pub fn minimize(args: Vec<Value>) -> Result {
+    optimize(args, 1.0)
+}
+
+/// Like [`minimize`], but finds a maximum. Returns a list `(argmax, value)`.
+pub fn maximize(args: Vec<Value>) -> Result {
+    optimize(args, -1.0)
+}
+
+/// minimize_bfgs(f, guess) uses the BFGS quasi-Newton method starting from `guess`, estimating
+/// the gradient with finite differences. This usually needs far fewer function evaluations than
+/// [`minimize`] for smooth functions of several variables.
+/// Requires a function and either one number or a list of numbers, always returns a list
+/// `(argmin, value)`.
+pub fn minimize_bfgs(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let (x0, scalar) = guess(&args[1])?;
+    let obj = Objective { func: &args[0], sign: 1.0 };
+    let (x, fx) = bfgs(&obj, x0)?;
+    Ok(obj.result(x, fx, scalar))
+}
+
+/// Like [`minimize_bfgs`], but finds a maximum. Returns a list `(argmax, value)`.
+pub fn maximize_bfgs(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let (x0, scalar) = guess(&args[1])?;
+    let obj = Objective { func: &args[0], sign: -1.0 };
+    let (x, fx) = bfgs(&obj, x0)?;
+    Ok(obj.result(x, fx, scalar))
+}
+
+fn optimize(args: Vec<Value>, sign: f64) -> Result {
+    bound_args(args.len(), 2, 3)?;
+    let obj = Objective { func: &args[0], sign };
+    if args.len() == 2 {
+        let (x0, scalar) = guess(&args[1])?;
+        let (x, fx) = nelder_mead(&obj, x0)?;
+        Ok(obj.result(x, fx, scalar))
+    } else {
+        let a = real(&args[1])?;
+        let b = real(&args[2])?;
+        if a >= b || a.is_nan() || b.is_nan() {
+            return Err(EvalErrorKind::WrongArgValue(args[2].clone()).into())
+        }
+        let (x, fx) = brent(&obj, a, b)?;
+        Ok(obj.result(vec![x], fx, true))
+    }
+}
+
+/// A function being minimized, with its results multiplied by `sign`
+struct Objective<'a> {
+    func: &'a Value,
+    sign: f64
+}
+
+impl Objective<'_> {
+    fn call(&self, x: &[f64]) -> std::result::Result<f64, EvalError> {
+        let args = x.iter().map(|v| Value::Float(*v)).collect();
+        Ok(self.sign * real(&self.func.eval(args)?)?)
+    }
+
+    fn result(&self, x: Vec<f64>, fx: f64, scalar: bool) -> Value {
+        let arg = if scalar {
+            Value::Float(x[0])
+        } else {
+            Value::List(x.into_iter().map(Value::Float).collect())
+        };
+        Value::List(vec![arg, Value::Float(self.sign * fx)])
+    }
+}
+
+fn real(val: &Value) -> std::result::Result<f64, EvalError> {
+    match to_float(val.clone())? {
+        Value::Float(x) => Ok(x),
+        _ => unreachable!()
+    }
+}
+
+fn guess(val: &Value) -> std::result::Result<(Vec<f64>, bool), EvalError> {
+    match val {
+        Value::List(l) if l.is_empty() => Err(EvalErrorKind::WrongArgValue(val.clone()).into()),
+        Value::List(l) => Ok((l.iter().map(real).collect::<std::result::Result<_, _>>()?, false)),
+        x => Ok((vec![real(x)?], true))
+    }
+}
+
+fn nelder_mead(obj: &Objective, x0: Vec<f64>) -> std::result::Result<(Vec<f64>, f64), EvalError> {
+    let n = x0.len();
+    let mut simplex = vec![(x0.clone(), obj.call(&x0)?)];
+    for i in 0..n {
+        let mut x = x0.clone();
+        x[i] = if x[i] == 0.0 { 0.00025 } else { 1.05 * x[i] };
+        let fx = obj.call(&x)?;
+        simplex.push((x, fx));
+    }
+    let lerp = |a: &[f64], b: &[f64], t: f64| -> Vec<f64> {
+        a.iter().zip(b).map(|(a, b)| a + t*(b - a)).collect()
+    };
+    for _ in 0..MAX_ITER*n {
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let (best, fbest) = simplex[0].clone();
+        let fworst = simplex[n].1;
+        let x_spread = simplex[1..].iter()
+            .flat_map(|(x, _)| x.iter().zip(&best).map(|(a, b)| (a - b).abs()))
+            .fold(0.0, f64::max);
+        let scale = best.iter().fold(1.0, |m, x| f64::max(m, x.abs()));
+        if (fworst - fbest).abs() <= 1e-12 * (1.0 + fbest.abs()) && x_spread <= 1e-9 * scale {
+            return Ok((best, fbest))
+        }
+        let mut centroid = vec![0.0; n];
+        for (x, _) in &simplex[..n] {
+            for (c, xi) in centroid.iter_mut().zip(x) {
+                *c += xi / n as f64;
+            }
+        }
+        let worst = simplex[n].0.clone();
+        let reflected = lerp(&centroid, &worst, -1.0);
+        let freflected = obj.call(&reflected)?;
+        if freflected < fbest {
+            let expanded = lerp(&centroid, &worst, -2.0);
+            let fexpanded = obj.call(&expanded)?;
+            simplex[n] = if fexpanded < freflected {
+                (expanded, fexpanded)
+            } else {
+                (reflected, freflected)
+            };
+        } else if freflected < simplex[n-1].1 {
+            simplex[n] = (reflected, freflected);
+        } else {
+            let (contracted, limit) = if freflected < fworst {
+                (lerp(&centroid, &worst, -0.5), freflected)
+            } else {
+                (lerp(&centroid, &worst, 0.5), fworst)
+            };
+            let fcontracted = obj.call(&contracted)?;
+            if fcontracted < limit {
+                simplex[n] = (contracted, fcontracted);
+            } else {
+                for point in simplex.iter_mut().skip(1) {
+                    let x = lerp(&best, &point.0, 0.5);
+                    let fx = obj.call(&x)?;
+                    *point = (x, fx);
+                }
+            }
+        }
+    }
+    Err(EvalErrorKind::NoConvergence(MAX_ITER*n).into())
+}
+
+fn brent(obj: &Objective, mut a: f64, mut b: f64) -> std::result::Result<(f64, f64), EvalError> {
+    let f = |x: f64| obj.call(&[x]);
+    let mut x = a + GOLDEN*(b - a);
+    let (mut w, mut v) = (x, x);
+    let mut fx = f(x)?;
+    let (mut fw, mut fv) = (fx, fx);
+    let (mut d, mut e): (f64, f64) = (0.0, 0.0);
+    for _ in 0..MAX_ITER {
+        let mid = 0.5*(a + b);
+        let tol1 = BRENT_TOL*x.abs() + 1e-12;
+        let tol2 = 2.0*tol1;
+        if (x - mid).abs() <= tol2 - 0.5*(b - a) {
+            return Ok((x, fx))
+        }
+        let mut golden = true;
+        if e.abs() > tol1 {
+            // try a parabolic step through x, w and v
+            let r = (x - w)*(fx - fv);
+            let mut q = (x - v)*(fx - fw);
+            let mut p = (x - v)*q - (x - w)*r;
+            q = 2.0*(q - r);
+            if q > 0.0 {
+                p = -p;
+            } else {
+                q = -q;
+            }
+            let prev_e = e;
+            e = d;
+            if p.abs() < (0.5*q*prev_e).abs() && p > q*(a - x) && p < q*(b - x) {
+                d = p/q;
+                let u = x + d;
+                if u - a < tol2 || b - u < tol2 {
+                    d = tol1.copysign(mid - x);
+                }
+                golden = false;
+            }
+        }
+        if golden {
+            e = if x >= mid { a - x } else { b - x };
+            d = GOLDEN*e;
+        }
+        let u = if d.abs() >= tol1 { x + d } else { x + tol1.copysign(d) };
+        let fu = f(u)?;
+        if fu <= fx {
+            if u >= x { a = x } else { b = x }
+            v = w; fv = fw;
+            w = x; fw = fx;
+            x = u; fx = fu;
+        } else {
+            if u < x { a = u } else { b = u }
+            if fu <= fw || w == x {
+                v = w; fv = fw;
+                w = u; fw = fu;
+            } else if fu <= fv || v == x || v == w {
+                v = u; fv = fu;
+            }
+        }
+    }
+    Err(EvalErrorKind::NoConvergence(MAX_ITER).into())
+}
+
+fn gradient(obj: &Objective, x: &[f64]) -> std::result::Result<Vec<f64>, EvalError> {
+    let mut grad = Vec::with_capacity(x.len());
+    let mut xh = x.to_vec();
+    for i in 0..x.len() {
+        let h = 6e-6 * x[i].abs().max(1.0);
+        xh[i] = x[i] + h;
+        let fplus = obj.call(&xh)?;
+        xh[i] = x[i] - h;
+        let fminus = obj.call(&xh)?;
+        xh[i] = x[i];
+        grad.push((fplus - fminus)/(2.0*h));
+    }
+    Ok(grad)
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a*b).sum()
+}
+
+fn bfgs(obj: &Objective, x0: Vec<f64>) -> std::result::Result<(Vec<f64>, f64), EvalError> {
+    let n = x0.len();
+    let identity = |n: usize| -> Vec<Vec<f64>> {
+        (0..n).map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect()).collect()
+    };
+    let mut x = x0;
+    let mut fx = obj.call(&x)?;
+    let mut g = gradient(obj, &x)?;
+    // approximation of the inverse Hessian
+    let mut h = identity(n);
+    for iter in 0..MAX_ITER {
+        if dot(&g, &g).sqrt() <= 1e-6 * (1.0 + fx.abs()) {
+            return Ok((x, fx))
+        }
+        let mut p: Vec<f64> = h.iter().map(|row| -dot(row, &g)).collect();
+        let mut slope = dot(&g, &p);
+        if slope >= 0.0 {
+            // not a descent direction, fall back to steepest descent
+            h = identity(n);
+            p = g.iter().map(|gi| -gi).collect();
+            slope = dot(&g, &p);
+        }
+        // backtracking line search with the Armijo condition
+        let mut alpha = 1.0;
+        let (xnew, fnew) = loop {
+            let xnew: Vec<f64> = x.iter().zip(&p).map(|(xi, pi)| xi + alpha*pi).collect();
+            let fnew = obj.call(&xnew)?;
+            if fnew <= fx + 1e-4*alpha*slope {
+                break (xnew, fnew)
+            }
+            alpha *= 0.5;
+            if alpha < 1e-16 {
+                // no further progress is possible at this precision, and the gradient is
+                // still too large for a minimum
+                return Err(EvalErrorKind::NoConvergence(iter + 1).into())
+            }
+        };
+        let gnew = gradient(obj, &xnew)?;
+        let s: Vec<f64> = xnew.iter().zip(&x).map(|(a, b)| a - b).collect();
+        let y: Vec<f64> = gnew.iter().zip(&g).map(|(a, b)| a - b).collect();
+        let sy = dot(&s, &y);
+        if sy > 1e-12 {
+            // H = (I - rho s y^T) H (I - rho y s^T) + rho s s^T
+            let rho = 1.0/sy;
+            let hy: Vec<f64> = h.iter().map(|row| dot(row, &y)).collect();
+            let yhy = dot(&y, &hy);
+            for i in 0..n {
+                for j in 0..n {
+                    h[i][j] += rho*((1.0 + rho*yhy)*s[i]*s[j] - hy[i]*s[j] - s[i]*hy[j]);
+                }
+            }
+        }
+        x = xnew;
+        fx = fnew;
+        g = gnew;
+    }
+    Err(EvalErrorKind::NoConvergence(MAX_ITER).into())
+}
@@ -1,37 +1,91 @@
 //! Can be disabled with the `random` feature flag
+//!
+//! Every function in this module draws from a shared generator. The functions in [`CTX_ALL`] use
+//! a process-wide generator seeded from system entropy, which can be reseeded from inside an
+//! expression with `seed(n)`. A host that needs reproducible results can instead build a context
+//! with its own generator using [`random_seeded`] or [`random_with_rng`].
 
 use crate::function::*;
 use crate::Value;
-use rand::Rng;
+use rand::{Rng, RngCore, SeedableRng};
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
+use std::sync::{Arc, Mutex};
+
+/// A shared handle to the generator used by the functions in a random context.
+pub type RngHandle = Arc<Mutex<Box<dyn RngCore + Send>>>;
 
 use crate::Context;
 lazy_static::lazy_static! {
+    static ref GLOBAL_RNG: RngHandle = Arc::new(Mutex::new(Box::new(StdRng::from_entropy())));
     /// A `lazy_static` [`Context`] containing all the definitions from [`rand`]
-    pub static ref CTX_ALL: Context = {
-        use crate::InsertFunction;
-        let mut ctx = Context::new();
-        ctx.insert_function("random".to_owned(), &random);
-        ctx.insert_function("random_range".to_owned(), &random_range);
-        ctx.insert_function("random_choose".to_owned(), &random_choose);
-        ctx.insert_function("shuffle".to_owned(), &shuffle);
-        ctx
-    };
+    pub static ref CTX_ALL: Context = ctx_with_handle(GLOBAL_RNG.clone());
+}
+
+/// Create a [`Context`] containing all the definitions from this module, drawing from a
+/// [`StdRng`] seeded with `seed`. Two contexts created with the same seed produce the same
+/// sequence of values.
+pub fn random_seeded(seed: u64) -> Context {
+    random_with_rng(StdRng::seed_from_u64(seed))
+}
+
+/// Create a [`Context`] containing all the definitions from this module, drawing from the
+/// given generator. Calling `seed(n)` from inside an expression replaces the generator with a
+/// [`StdRng`] seeded with `n`.
+pub fn random_with_rng<R: RngCore + Send + 'static>(rng: R) -> Context {
+    ctx_with_handle(Arc::new(Mutex::new(Box::new(rng))))
+}
+
+fn ctx_with_handle(rng: RngHandle) -> Context {
+    let mut ctx = Context::new();
+    insert_bound(&mut ctx, "random", &rng, random_inner);
+    insert_bound(&mut ctx, "random_range", &rng, random_range_inner);
+    insert_bound(&mut ctx, "random_choose", &rng, random_choose_inner);
+    insert_bound(&mut ctx, "shuffle", &rng, shuffle_inner);
+    insert_bound(&mut ctx, "seed", &rng, seed_inner);
+    ctx
+}
+
+fn insert_bound(ctx: &mut Context, name: &str, rng: &RngHandle, func: fn(&RngHandle, Vec<Value>) -> Result) {
+    let rng = rng.clone();
+    ctx.insert(name.to_owned(), Value::Function(Function(Arc::new(move |args| func(&rng, args)))));
 }
 
 pub fn random(args: Vec<Value>) -> Result {
-    max_args(args.len(), 0)?;
-    Ok(Value::Float(rand::random()))
+    random_inner(&GLOBAL_RNG, args)
 }
 
 pub fn random_range(args: Vec<Value>) -> Result {
+    random_range_inner(&GLOBAL_RNG, args)
+}
+
+pub fn random_choose(args: Vec<Value>) -> Result {
+    random_choose_inner(&GLOBAL_RNG, args)
+}
+
+pub fn shuffle(args: Vec<Value>) -> Result {
+    shuffle_inner(&GLOBAL_RNG, args)
+}
+
+/// Reseed the process-wide generator. Requires exactly one [`Value::Integer`] argument, always
+/// returns [`Value::Void`].
+pub fn seed(args: Vec<Value>) -> Result {
+    seed_inner(&GLOBAL_RNG, args)
+}
+
+fn random_inner(rng: &RngHandle, args: Vec<Value>) -> Result {
+    max_args(args.len(), 0)?;
+    Ok(Value::Float(rng.lock().unwrap().gen()))
+}
+
+fn random_range_inner(rng: &RngHandle, args: Vec<Value>) -> Result {
     bound_args(args.len(), 1, 2)?;
     if args.len() == 1 {
         if let Value::Integer(max) = &args[0] {
             if max <= &0 {
                 return Ok(Value::Void)
             }
-            Ok(Value::Integer(rand::thread_rng().gen_range(0..*max)))
+            Ok(Value::Integer(rng.lock().unwrap().gen_range(0..*max)))
         } else {
             Err(EvalErrorKind::WrongArgType(args[0].clone()).into())
         }
@@ -41,7 +95,7 @@ pub fn random_range(args: Vec<Value>) -> Result {
                 if min >= max {
                     return Ok(Value::Void)
                 }
-                Ok(Value::Integer(rand::thread_rng().gen_range(*min..*max)))
+                Ok(Value::Integer(rng.lock().unwrap().gen_range(*min..*max)))
             } else {
                 Err(EvalErrorKind::WrongArgType(args[1].clone()).into())
             }
@@ -51,25 +105,28 @@ pub fn random_range(args: Vec<Value>) -> Result {
     }
 }
 
-pub fn random_choose(args: Vec<Value>) -> Result {
+fn random_choose_inner(rng: &RngHandle, args: Vec<Value>) -> Result {
     bound_args(args.len(), 1, 1)?;
-    if let Value::List(l) = &args[0] {
-        if l.len() == 0 {
-            return Ok(Value::Void)
-        }
-        let idx = rand::thread_rng().gen_range(0..l.len());
-        Ok(l[idx].clone())
-    } else {
-        Err(EvalErrorKind::WrongArgType(args[0].clone()).into())
+    let l = list_arg(&args[0])?;
+    if l.len() == 0 {
+        return Ok(Value::Void)
     }
+    let idx = rng.lock().unwrap().gen_range(0..l.len());
+    Ok(l[idx].clone())
 }
 
-pub fn shuffle(args: Vec<Value>) -> Result {
+fn shuffle_inner(rng: &RngHandle, args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    let mut l = list_arg(&args[0])?.into_owned();
+    l.shuffle(&mut *rng.lock().unwrap());
+    Ok(Value::List(l))
+}
+
+fn seed_inner(rng: &RngHandle, args: Vec<Value>) -> Result {
     bound_args(args.len(), 1, 1)?;
-    if let Value::List(l) = &args[0] {
-        let mut l = l.clone();
-        l.shuffle(&mut rand::thread_rng());
-        Ok(Value::List(l))
+    if let Value::Integer(n) = args[0] {
+        *rng.lock().unwrap() = Box::new(StdRng::seed_from_u64(n as u64));
+        Ok(Value::Void)
     } else {
         Err(EvalErrorKind::WrongArgType(args[0].clone()).into())
     }
@@ -0,0 +1,164 @@
+//! Regular expressions, using the syntax of the [`regex`] crate. Every function accepts either a
+//! pattern string or a compiled pattern returned by [`re_compile`]. Pattern strings are compiled
+//! once and cached, but using [`re_compile`] avoids the cache lookup entirely.
+
+use crate::function::*;
+use crate::function::string::str_arg;
+use crate::Value;
+use crate::value::ValueBuiltin;
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Maximum number of pattern strings kept in the cache before it is cleared
+const CACHE_SIZE: usize = 256;
+
+use crate::Context;
+lazy_static::lazy_static! {
+    /// A `lazy_static` [`Context`] containing all the definitions from [`re`]
+    pub static ref CTX_ALL: Context = {
+        use crate::InsertFunction;
+        let mut ctx = Context::new();
+        ctx.insert_function("re_compile".to_owned(), &re_compile);
+        ctx.insert_function("re_match".to_owned(), &re_match);
+        ctx.insert_function("re_find_all".to_owned(), &re_find_all);
+        ctx.insert_function("re_captures".to_owned(), &re_captures);
+        ctx.insert_function("re_named".to_owned(), &re_named);
+        ctx.insert_function("re_replace".to_owned(), &re_replace);
+        ctx.insert_function("re_split".to_owned(), &re_split);
+        ctx
+    };
+    static ref CACHE: Mutex<HashMap<String, Regex>> = Mutex::new(HashMap::new());
+}
+
+/// A compiled pattern, stored in a [`Value::Builtin`]
+pub struct CompiledRegex(pub Regex);
+
+impl ValueBuiltin for CompiledRegex {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any { self }
+}
+
+fn get_regex(val: &Value) -> std::result::Result<Regex, EvalError> {
+    match val {
+        Value::Str(pattern) => {
+            let mut cache = CACHE.lock().unwrap();
+            if let Some(re) = cache.get(pattern) {
+                return Ok(re.clone())
+            }
+            match Regex::new(pattern) {
+                Ok(re) => {
+                    if cache.len() >= CACHE_SIZE {
+                        cache.clear();
+                    }
+                    cache.insert(pattern.to_owned(), re.clone());
+                    Ok(re)
+                },
+                Err(e) => Err(EvalErrorKind::Other(format!("Invalid regular expression: {}", e)).into())
+            }
+        },
+        Value::Builtin(b) => match b.as_any().downcast_ref::<CompiledRegex>() {
+            Some(re) => Ok(re.0.clone()),
+            None => Err(EvalErrorKind::WrongArgType(val.clone()).into())
+        },
+        _ => Err(EvalErrorKind::WrongArgType(val.clone()).into())
+    }
+}
+
+/// Groups of a match as a list, with [`Value::Void`] for groups that did not participate
+fn groups_list(caps: &Captures) -> Value {
+    Value::List(caps.iter().map(|m| match m {
+        Some(m) => Value::Str(m.as_str().to_owned()),
+        None => Value::Void
+    }).collect())
+}
+
+/// Compiles a pattern so it can be reused without recompiling.
+/// Requires exactly one [`Value::Str`] argument, always returns a [`Value::Builtin`].
+pub fn re_compile(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    let re = get_regex(&args[0])?;
+    Ok(Value::Builtin(Arc::new(CompiledRegex(re))))
+}
+
+/// Checks if the pattern matches anywhere in the string. Use `^` and `$` to match the whole
+/// string. Requires a pattern and a [`Value::Str`], always returns a [`Value::Bool`].
+pub fn re_match(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let re = get_regex(&args[0])?;
+    Ok(Value::Bool(re.is_match(str_arg(&args[1])?)))
+}
+
+/// Finds all non-overlapping matches of the pattern.
+/// Requires a pattern and a [`Value::Str`], always returns a list of [`Value::Str`].
+pub fn re_find_all(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let re = get_regex(&args[0])?;
+    let s = str_arg(&args[1])?;
+    Ok(Value::List(re.find_iter(s).map(|m| Value::Str(m.as_str().to_owned())).collect()))
+}
+
+/// Returns the groups of the first match of the pattern as a list, where index 0 is the whole
+/// match and unmatched groups are [`Value::Void`].
+/// Requires a pattern and a [`Value::Str`], returns a list or [`Value::Void`] if there is no match.
+pub fn re_captures(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let re = get_regex(&args[0])?;
+    match re.captures(str_arg(&args[1])?) {
+        Some(caps) => Ok(groups_list(&caps)),
+        None => Ok(Value::Void)
+    }
+}
+
+/// Returns the named groups of the first match of the pattern as a list of `(name, value)`
+/// pairs, in the same format as `$ctx`. Unmatched groups are [`Value::Void`].
+/// Requires a pattern and a [`Value::Str`], returns a list or [`Value::Void`] if there is no match.
+pub fn re_named(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let re = get_regex(&args[0])?;
+    match re.captures(str_arg(&args[1])?) {
+        Some(caps) => Ok(Value::List(re.capture_names().flatten().map(|name| {
+            let val = match caps.name(name) {
+                Some(m) => Value::Str(m.as_str().to_owned()),
+                None => Value::Void
+            };
+            Value::List(vec![Value::Str(name.to_owned()), val])
+        }).collect())),
+        None => Ok(Value::Void)
+    }
+}
+
+/// Replaces every match of the pattern. The replacement is either a [`Value::Str`], which may
+/// refer to groups with `$1` or `${name}`, or a function that is called with the list of groups
+/// of each match (as returned by [`re_captures`]) and whose result is converted to a string.
+/// Requires a pattern, a [`Value::Str`], and a string or function, always returns a [`Value::Str`].
+pub fn re_replace(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 3, 3)?;
+    let re = get_regex(&args[0])?;
+    let s = str_arg(&args[1])?;
+    match &args[2] {
+        Value::Str(rep) => Ok(Value::Str(re.replace_all(s, rep.as_str()).into_owned())),
+        func if func.is_callable() => {
+            let mut res = String::new();
+            let mut last = 0;
+            for caps in re.captures_iter(s) {
+                let m = caps.get(0).unwrap();
+                res += &s[last..m.start()];
+                res += &func.eval(vec![groups_list(&caps)])?.to_string();
+                last = m.end();
+            }
+            res += &s[last..];
+            Ok(Value::Str(res))
+        },
+        x => Err(EvalErrorKind::WrongArgType(x.clone()).into())
+    }
+}
+
+/// Splits a string on every match of the pattern.
+/// Requires a pattern and a [`Value::Str`], always returns a list of [`Value::Str`].
+pub fn re_split(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let re = get_regex(&args[0])?;
+    let s = str_arg(&args[1])?;
+    Ok(Value::List(re.split(s).map(|x| Value::Str(x.to_owned())).collect()))
+}
@@ -0,0 +1,51 @@
+//! Mutable reference cells, for state shared between calls of a function. Copies of a
+//! reference refer to the same cell, so a lambda can keep a counter or a cache in one.
+
+use crate::function::*;
+use crate::Value;
+
+use crate::Context;
+lazy_static::lazy_static! {
+    /// A `lazy_static` [`Context`] containing all the definitions from [`refs`]
+    pub static ref CTX_ALL: Context = {
+        use crate::InsertFunction;
+        let mut ctx = Context::new();
+        ctx.insert_function("ref".to_owned(), &new_ref);
+        ctx.insert_function("deref".to_owned(), &deref);
+        ctx.insert_function("update".to_owned(), &update);
+        ctx
+    };
+}
+
+fn ref_arg(val: &Value) -> std::result::Result<Value, EvalError> {
+    match val.get_ref() {
+        Some(v) => Ok(v),
+        None => Err(EvalErrorKind::WrongArgType(val.clone()).into())
+    }
+}
+
+/// Creates a reference holding the argument, or void if there is none. The value can be
+/// read with [`deref`] and replaced with `r := v` or [`update`].
+pub fn new_ref(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 0, 1)?;
+    Ok(Value::new_ref(args.into_iter().next().unwrap_or(Value::Void)))
+}
+
+/// Returns the value held by a reference.
+pub fn deref(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    ref_arg(&args[0])
+}
+
+/// Replaces the value held by a reference `r` with `f(value, ...args)` and returns the new
+/// value. `f` can use `r` itself, but another change to `r` while `f` runs is overwritten.
+pub fn update(args: Vec<Value>) -> Result {
+    min_args(args.len(), 2)?;
+    let mut args = args.into_iter();
+    let (r, f) = (args.next().unwrap(), args.next().unwrap());
+    let mut fargs = vec![ref_arg(&r)?];
+    fargs.extend(args);
+    let res = f.eval(fargs)?;
+    r.set_ref(res.clone())?;
+    Ok(res)
+}
@@ -0,0 +1,273 @@
+//! Discrete Fourier transforms, convolution, and window functions. Transforms accept lists of
+//! any numerical type and always return lists of [`Value::Complex`].
+
+use crate::function::*;
+use crate::Value;
+use crate::value::{Complex, r2f64};
+use std::f64::consts::PI;
+
+use crate::Context;
+lazy_static::lazy_static! {
+    /// A `lazy_static` [`Context`] containing all the definitions from [`signal`]
+    pub static ref CTX_ALL: Context = {
+        use crate::InsertFunction;
+        let mut ctx = Context::new();
+        ctx.insert_function("fft".to_owned(), &fft);
+        ctx.insert_function("ifft".to_owned(), &ifft);
+        ctx.insert_function("rfft".to_owned(), &rfft);
+        ctx.insert_function("dft".to_owned(), &dft);
+        ctx.insert_function("convolve".to_owned(), &convolve);
+        ctx.insert_function("correlate".to_owned(), &correlate);
+        ctx.insert_function("hann".to_owned(), &hann);
+        ctx.insert_function("hamming".to_owned(), &hamming);
+        ctx.insert_function("blackman".to_owned(), &blackman);
+        ctx.insert_function("fftfreq".to_owned(), &fftfreq);
+        ctx
+    };
+}
+
+/// Computes the discrete Fourier transform of a list. Lists whose length is a power of two use a
+/// radix-2 FFT, other lengths use Bluestein's algorithm, so every length runs in `O(n log n)`.
+/// Requires exactly one list of numbers, always returns a list of [`Value::Complex`].
+pub fn fft(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    let data = to_complex_list(&args[0])?;
+    Ok(from_complex_list(transform(&data, false)))
+}
+
+/// Computes the inverse discrete Fourier transform of a list, normalized so that `ifft(fft(x))`
+/// is `x`. Requires exactly one list of numbers, always returns a list of [`Value::Complex`].
+pub fn ifft(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    let data = to_complex_list(&args[0])?;
+    let scale = 1.0 / data.len().max(1) as f64;
+    Ok(from_complex_list(transform(&data, true).into_iter().map(|x| x * scale).collect()))
+}
+
+/// Computes the discrete Fourier transform of a list of real numbers, returning only the
+/// `n/2 + 1` non-negative frequency terms.
+/// Requires exactly one list of real numbers, always returns a list of [`Value::Complex`].
+pub fn rfft(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    let data = to_complex_list(&args[0])?;
+    if data.iter().any(|x| x.im != 0.0) {
+        return Err(EvalErrorKind::WrongArgValue(args[0].clone()).into())
+    }
+    let len = data.len();
+    let mut res = transform(&data, false);
+    res.truncate(len/2 + 1);
+    Ok(from_complex_list(res))
+}
+
+/// Computes the discrete Fourier transform of a list directly from the definition. This is
+/// `O(n^2)`, prefer [`fft`] except for very short lists.
+/// Requires exactly one list of numbers, always returns a list of [`Value::Complex`].
+pub fn dft(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    let data = to_complex_list(&args[0])?;
+    let n = data.len();
+    let res = (0..n).map(|k| {
+        data.iter().enumerate()
+            .map(|(j, x)| x * twiddle((j*k) % n, n, false))
+            .sum()
+    }).collect();
+    Ok(from_complex_list(res))
+}
+
+/// Computes the full linear convolution of two lists, of length `len(a) + len(b) - 1`.
+/// Requires exactly two lists of numbers, always returns a list of [`Value::Complex`].
+pub fn convolve(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let a = to_complex_list(&args[0])?;
+    let b = to_complex_list(&args[1])?;
+    Ok(from_complex_list(convolve_inner(&a, &b)))
+}
+
+/// Computes the full cross-correlation of two lists, `c[k] = sum(a[n+k] * conj(b[n]))`, for lags
+/// from `-(len(b) - 1)` to `len(a) - 1`.
+/// Requires exactly two lists of numbers, always returns a list of [`Value::Complex`].
+pub fn correlate(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let a = to_complex_list(&args[0])?;
+    let b: Vec<Complex> = to_complex_list(&args[1])?.iter().rev().map(|x| x.conj()).collect();
+    Ok(from_complex_list(convolve_inner(&a, &b)))
+}
+
+/// Returns a symmetric Hann window of the given length.
+/// Requires exactly one nonnegative integer argument, always returns a list of [`Value::Float`].
+pub fn hann(args: Vec<Value>) -> Result {
+    window(args, &[0.5, 0.5])
+}
+
+/// Returns a symmetric Hamming window of the given length.
+/// Requires exactly one nonnegative integer argument, always returns a list of [`Value::Float`].
+pub fn hamming(args: Vec<Value>) -> Result {
+    window(args, &[0.54, 0.46])
+}
+
+/// Returns a symmetric Blackman window of the given length.
+/// Requires exactly one nonnegative integer argument, always returns a list of [`Value::Float`].
+pub fn blackman(args: Vec<Value>) -> Result {
+    window(args, &[0.42, 0.5, 0.08])
+}
+
+/// Returns the sample frequencies for the output of [`fft`]. The first argument is the length of
+/// the transform, the optional second argument is the sample spacing (defaulting to 1).
+/// Requires one positive integer and optionally one real argument, always returns a list of
+/// [`Value::Float`].
+pub fn fftfreq(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 2)?;
+    let n = match args[0] {
+        Value::Integer(n) if n > 0 => n,
+        Value::Integer(_) => return Err(EvalErrorKind::WrongArgValue(args[0].clone()).into()),
+        _ => return Err(EvalErrorKind::WrongArgType(args[0].clone()).into())
+    };
+    let d = match args.get(1) {
+        Some(x) => match to_float(x.clone())? {
+            Value::Float(d) => d,
+            _ => unreachable!()
+        },
+        None => 1.0
+    };
+    let scale = 1.0 / (d * n as f64);
+    let res = (0..n)
+        .map(|k| if k < (n + 1)/2 { k } else { k - n })
+        .map(|k| Value::Float(k as f64 * scale))
+        .collect();
+    Ok(Value::List(res))
+}
+
+fn window(args: Vec<Value>, coeffs: &[f64]) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    let n = match args[0] {
+        Value::Integer(n) if n >= 0 => n as usize,
+        Value::Integer(_) => return Err(EvalErrorKind::WrongArgValue(args[0].clone()).into()),
+        _ => return Err(EvalErrorKind::WrongArgType(args[0].clone()).into())
+    };
+    if n == 1 {
+        return Ok(Value::List(vec![Value::Float(1.0)]))
+    }
+    let res = (0..n).map(|k| {
+        let x = 2.0 * PI * k as f64 / (n - 1) as f64;
+        let w = coeffs.iter().enumerate()
+            .map(|(i, c)| {
+                let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+                sign * c * (i as f64 * x).cos()
+            })
+            .sum();
+        Value::Float(w)
+    }).collect();
+    Ok(Value::List(res))
+}
+
+fn to_complex_list(val: &Value) -> std::result::Result<Vec<Complex>, EvalError> {
+    list_arg(val)?.iter().map(|x| match x {
+        Value::Integer(n) => Ok(Complex::new(*n as f64, 0.0)),
+        Value::Float(n) => Ok(Complex::new(*n, 0.0)),
+        Value::Ratio(n) => Ok(Complex::new(r2f64(n), 0.0)),
+        Value::Complex(n) => Ok(*n),
+        _ => Err(EvalErrorKind::WrongArgType(x.clone()).into())
+    }).collect()
+}
+
+fn from_complex_list(list: Vec<Complex>) -> Value {
+    Value::List(list.into_iter().map(Value::Complex).collect())
+}
+
+/// `exp(-2 pi i k / n)`, or its conjugate for the inverse transform
+fn twiddle(k: usize, n: usize, inverse: bool) -> Complex {
+    let sign = if inverse { 1.0 } else { -1.0 };
+    Complex::from_polar(1.0, sign * 2.0 * PI * k as f64 / n as f64)
+}
+
+/// Unnormalized transform of any length
+fn transform(data: &[Complex], inverse: bool) -> Vec<Complex> {
+    let n = data.len();
+    if n <= 1 {
+        data.to_vec()
+    } else if n.is_power_of_two() {
+        let mut res = data.to_vec();
+        fft_radix2(&mut res, inverse);
+        res
+    } else {
+        bluestein(data, inverse)
+    }
+}
+
+/// In-place iterative radix-2 FFT, `data.len()` must be a power of two
+fn fft_radix2(data: &mut [Complex], inverse: bool) {
+    let n = data.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let w = twiddle(1, len, inverse);
+        for start in (0..n).step_by(len) {
+            let mut wk = Complex::new(1.0, 0.0);
+            for k in 0..len/2 {
+                let u = data[start + k];
+                let v = data[start + k + len/2] * wk;
+                data[start + k] = u + v;
+                data[start + k + len/2] = u - v;
+                wk *= w;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Bluestein's algorithm, expressing a transform of any length as a convolution of
+/// power-of-two length
+fn bluestein(data: &[Complex], inverse: bool) -> Vec<Complex> {
+    let n = data.len();
+    let m = (2*n - 1).next_power_of_two();
+    // chirp[k] = exp(-pi i k^2 / n), k^2 is reduced mod 2n to keep the angle accurate
+    let chirp: Vec<Complex> = (0..n)
+        .map(|k| twiddle((k*k) % (2*n), 2*n, inverse))
+        .collect();
+    let mut a = vec![Complex::new(0.0, 0.0); m];
+    for k in 0..n {
+        a[k] = data[k] * chirp[k];
+    }
+    let mut b = vec![Complex::new(0.0, 0.0); m];
+    b[0] = chirp[0].conj();
+    for k in 1..n {
+        b[k] = chirp[k].conj();
+        b[m - k] = chirp[k].conj();
+    }
+    fft_radix2(&mut a, false);
+    fft_radix2(&mut b, false);
+    let mut conv: Vec<Complex> = a.iter().zip(b.iter()).map(|(x, y)| x * y).collect();
+    fft_radix2(&mut conv, true);
+    let scale = 1.0 / m as f64;
+    (0..n).map(|k| conv[k] * scale * chirp[k]).collect()
+}
+
+fn convolve_inner(a: &[Complex], b: &[Complex]) -> Vec<Complex> {
+    if a.is_empty() || b.is_empty() {
+        return vec![]
+    }
+    let len = a.len() + b.len() - 1;
+    let m = len.next_power_of_two();
+    let mut fa = a.to_vec();
+    fa.resize(m, Complex::new(0.0, 0.0));
+    let mut fb = b.to_vec();
+    fb.resize(m, Complex::new(0.0, 0.0));
+    fft_radix2(&mut fa, false);
+    fft_radix2(&mut fb, false);
+    let mut res: Vec<Complex> = fa.iter().zip(fb.iter()).map(|(x, y)| x * y).collect();
+    fft_radix2(&mut res, true);
+    let scale = 1.0 / m as f64;
+    res.truncate(len);
+    res.into_iter().map(|x| x * scale).collect()
+}
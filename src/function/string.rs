@@ -0,0 +1,218 @@
+//! Functions for manipulating strings. Positions and lengths are always counted in Unicode
+//! characters, never in bytes.
+
+use crate::function::*;
+use crate::Value;
+
+use crate::Context;
+lazy_static::lazy_static! {
+    /// A `lazy_static` [`Context`] containing all the definitions from [`string`]
+    pub static ref CTX_ALL: Context = {
+        use crate::InsertFunction;
+        let mut ctx = Context::new();
+        ctx.insert_function("split".to_owned(), &split);
+        ctx.insert_function("join".to_owned(), &join);
+        ctx.insert_function("replace".to_owned(), &replace);
+        ctx.insert_function("trim".to_owned(), &trim);
+        ctx.insert_function("upper".to_owned(), &upper);
+        ctx.insert_function("lower".to_owned(), &lower);
+        ctx.insert_function("starts_with".to_owned(), &starts_with);
+        ctx.insert_function("ends_with".to_owned(), &ends_with);
+        ctx.insert_function("contains".to_owned(), &super::util::contains);
+        ctx.insert_function("find".to_owned(), &super::util::find);
+        ctx.insert_function("pad_left".to_owned(), &pad_left);
+        ctx.insert_function("pad_right".to_owned(), &pad_right);
+        ctx.insert_function("lines".to_owned(), &lines);
+        ctx.insert_function("words".to_owned(), &words);
+        ctx.insert_function("format".to_owned(), &format);
+        ctx
+    };
+}
+
+pub(crate) fn str_arg(val: &Value) -> std::result::Result<&str, EvalError> {
+    match val {
+        Value::Str(s) => Ok(s),
+        _ => Err(EvalErrorKind::WrongArgType(val.clone()).into())
+    }
+}
+
+fn str_list<'a, I: Iterator<Item = &'a str>>(iter: I) -> Value {
+    Value::List(iter.map(|s| Value::Str(s.to_owned())).collect())
+}
+
+/// Splits a string on every occurrence of a separator.
+/// Requires two [`Value::Str`] arguments, always returns a list of [`Value::Str`].
+pub fn split(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let s = str_arg(&args[0])?;
+    let sep = str_arg(&args[1])?;
+    if sep.is_empty() {
+        return Err(EvalErrorKind::WrongArgValue(args[1].clone()).into())
+    }
+    Ok(str_list(s.split(sep)))
+}
+
+/// Joins a list of values into a string, converting each with [`ToString`] and placing the
+/// separator (if given) between them.
+/// Requires a list and optionally a [`Value::Str`], always returns a [`Value::Str`].
+pub fn join(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 2)?;
+    let sep = match args.get(1) {
+        Some(x) => str_arg(x)?,
+        None => ""
+    };
+    let l = list_arg(&args[0])?;
+    Ok(Value::Str(l.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(sep)))
+}
+
+/// Replaces every occurrence of the second argument in the first with the third.
+/// Requires three [`Value::Str`] arguments, always returns a [`Value::Str`].
+pub fn replace(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 3, 3)?;
+    let s = str_arg(&args[0])?;
+    let from = str_arg(&args[1])?;
+    let to = str_arg(&args[2])?;
+    Ok(Value::Str(s.replace(from, to)))
+}
+
+/// Removes leading and trailing whitespace.
+/// Requires exactly one [`Value::Str`] argument, always returns a [`Value::Str`].
+pub fn trim(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    Ok(Value::Str(str_arg(&args[0])?.trim().to_owned()))
+}
+
+/// Converts a string to uppercase.
+/// Requires exactly one [`Value::Str`] argument, always returns a [`Value::Str`].
+pub fn upper(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    Ok(Value::Str(str_arg(&args[0])?.to_uppercase()))
+}
+
+/// Converts a string to lowercase.
+/// Requires exactly one [`Value::Str`] argument, always returns a [`Value::Str`].
+pub fn lower(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    Ok(Value::Str(str_arg(&args[0])?.to_lowercase()))
+}
+
+/// Checks if the first string starts with the second.
+/// Requires two [`Value::Str`] arguments, always returns a [`Value::Bool`].
+pub fn starts_with(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    Ok(Value::Bool(str_arg(&args[0])?.starts_with(str_arg(&args[1])?)))
+}
+
+/// Checks if the first string ends with the second.
+/// Requires two [`Value::Str`] arguments, always returns a [`Value::Bool`].
+pub fn ends_with(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    Ok(Value::Bool(str_arg(&args[0])?.ends_with(str_arg(&args[1])?)))
+}
+
+/// Checks if the first string contains the second. Registered as part of
+/// [`super::util::contains`], which also accepts lists, in this module's context as well.
+/// Requires two [`Value::Str`] arguments, always returns a [`Value::Bool`].
+pub fn contains(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    Ok(Value::Bool(str_arg(&args[0])?.contains(str_arg(&args[1])?)))
+}
+
+/// Finds the character index of the first occurrence of the second string in the first.
+/// Registered as part of [`super::util::find`], which also accepts lists, in this module's
+/// context as well.
+/// Requires two [`Value::Str`] arguments, returns a [`Value::Integer`] or [`Value::Void`] if
+/// the string is not found.
+pub fn find(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let s = str_arg(&args[0])?;
+    match s.find(str_arg(&args[1])?) {
+        Some(idx) => Ok(Value::Integer(s[..idx].chars().count() as i64)),
+        None => Ok(Value::Void)
+    }
+}
+
+/// Pads the start of a string to the given width using a fill character (a space by default).
+/// Strings already at least as long as the width are returned unchanged.
+/// Requires a [`Value::Str`], a nonnegative [`Value::Integer`], and optionally a single-character
+/// [`Value::Str`], always returns a [`Value::Str`].
+pub fn pad_left(args: Vec<Value>) -> Result {
+    let (s, padding) = pad_args(&args)?;
+    Ok(Value::Str(padding + s))
+}
+
+/// Pads the end of a string to the given width, see [`pad_left`].
+pub fn pad_right(args: Vec<Value>) -> Result {
+    let (s, padding) = pad_args(&args)?;
+    Ok(Value::Str(s.to_owned() + &padding))
+}
+
+fn pad_args(args: &[Value]) -> std::result::Result<(&str, String), EvalError> {
+    bound_args(args.len(), 2, 3)?;
+    let s = str_arg(&args[0])?;
+    let width = match args[1] {
+        Value::Integer(n) if n >= 0 => n as usize,
+        Value::Integer(_) => return Err(EvalErrorKind::WrongArgValue(args[1].clone()).into()),
+        _ => return Err(EvalErrorKind::WrongArgType(args[1].clone()).into())
+    };
+    let fill = match args.get(2) {
+        Some(x) => {
+            let mut chars = str_arg(x)?.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => return Err(EvalErrorKind::WrongArgValue(x.clone()).into())
+            }
+        },
+        None => ' '
+    };
+    crate::limits::check_len(width)?;
+    let count = width.saturating_sub(s.chars().count());
+    Ok((s, fill.to_string().repeat(count)))
+}
+
+/// Splits a string into lines, accepting both `\n` and `\r\n` line endings.
+/// Requires exactly one [`Value::Str`] argument, always returns a list of [`Value::Str`].
+pub fn lines(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    Ok(str_list(str_arg(&args[0])?.lines()))
+}
+
+/// Splits a string into words separated by any amount of whitespace.
+/// Requires exactly one [`Value::Str`] argument, always returns a list of [`Value::Str`].
+pub fn words(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    Ok(str_list(str_arg(&args[0])?.split_whitespace()))
+}
+
+/// Replaces each `{}` in the format string with the next argument, converted with [`ToString`].
+/// `{{` and `}}` produce literal braces.
+/// Requires one [`Value::Str`] argument followed by exactly as many arguments as there are
+/// placeholders, always returns a [`Value::Str`].
+pub fn format(args: Vec<Value>) -> Result {
+    min_args(args.len(), 1)?;
+    let fmt = str_arg(&args[0])?;
+    // literal pieces between placeholders, there is always one more piece than placeholders
+    let mut pieces = vec![String::new()];
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                pieces.last_mut().unwrap().push(c);
+            },
+            ('{', Some('}')) => {
+                chars.next();
+                pieces.push(String::new());
+            },
+            ('{', _) | ('}', _) => return Err(EvalErrorKind::WrongArgValue(args[0].clone()).into()),
+            _ => pieces.last_mut().unwrap().push(c)
+        }
+    }
+    bound_args(args.len(), pieces.len(), pieces.len())?;
+    let mut res = pieces[0].clone();
+    for (value, piece) in args[1..].iter().zip(&pieces[1..]) {
+        res += &value.to_string();
+        res += piece;
+    }
+    Ok(Value::Str(res))
+}
@@ -1,6 +1,7 @@
 use crate::function::*;
 use crate::Value;
 use crate::value::Ratio;
+use crate::format::FormatSpec;
 
 use crate::Context;
 lazy_static::lazy_static! {
@@ -15,6 +16,7 @@ lazy_static::lazy_static! {
         ctx.insert_function("is_complex".to_owned(), &is_complex);
         ctx.insert_function("is_bool".to_owned(), &is_bool);
         ctx.insert_function("is_list".to_owned(), &is_list);
+        ctx.insert_function("is_ref".to_owned(), &is_ref);
         ctx.insert_function("is_callable".to_owned(), &is_callable);
         ctx.insert_function("is_infinite".to_owned(), &is_infinite);
         ctx.insert_function("is_nan".to_owned(), &is_nan);
@@ -23,6 +25,8 @@ lazy_static::lazy_static! {
         ctx.insert_function("to_ratio".to_owned(), &to_ratio);
         ctx.insert_function("to_str".to_owned(), &to_str);
         ctx.insert_function("to_repr".to_owned(), &to_repr);
+        ctx.insert_function("format_num".to_owned(), &format_num);
+        ctx.insert_function("set_display".to_owned(), &set_display);
         ctx
     };
 }
@@ -69,6 +73,12 @@ pub fn is_list(args: Vec<Value>) -> Result {
     bound_args(args.len(), 1, 1)?;
     Ok(Value::Bool(args[0].is_list()))
 }
+/// Checks if a value is a [`Value::Ref`].
+/// Requires exactly one argument of any type, always returns a [`Value::Bool`].
+pub fn is_ref(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    Ok(Value::Bool(args[0].is_ref()))
+}
 /// Checks if a value is a [`Value::Str`].
 /// Requires exactly one argument of any type, always returns a [`Value::Bool`].
 pub fn is_str(args: Vec<Value>) -> Result {
@@ -158,3 +168,37 @@ pub fn to_repr(args: Vec<Value>) -> Result {
     bound_args(args.len(), 1, 1)?;
     Ok(Value::Str(format!("{:?}", args[0])))
 }
+
+fn format_spec(val: &Value) -> std::result::Result<FormatSpec, EvalError> {
+    if let Value::Str(s) = val {
+        FormatSpec::parse(s).ok_or_else(|| EvalErrorKind::WrongArgValue(val.clone()).into())
+    } else {
+        Err(EvalErrorKind::WrongArgType(val.clone()).into())
+    }
+}
+
+/// Convert a number to a string using a format specification (see [`FormatSpec`]), for example
+/// `format_num(1234.5, ",.2f")` is `"1,234.50"`.
+/// Requires a number and a [`Value::Str`], always returns a [`Value::Str`].
+pub fn format_num(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    match args[0] {
+        Value::Integer(_) | Value::Float(_) | Value::Ratio(_) | Value::Complex(_) => (),
+        _ => return Err(EvalErrorKind::WrongArgType(args[0].clone()).into())
+    }
+    Ok(Value::Str(format_spec(&args[1])?.format(&args[0])?))
+}
+
+/// Set the format used to display floats and complex numbers in the context being evaluated
+/// (see [`crate::Context::set_display`]). The empty string restores the default. Width, alignment and
+/// zero padding are not allowed.
+/// Requires exactly one [`Value::Str`] argument, always returns [`Value::Void`].
+pub fn set_display(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    let spec = format_spec(&args[0])?;
+    if spec.width != 0 || spec.align.is_some() || spec.zero {
+        return Err(EvalErrorKind::WrongArgValue(args[0].clone()).into())
+    }
+    crate::format::set_current(spec)?;
+    Ok(Value::Void)
+}
@@ -1,5 +1,8 @@
 use crate::function::*;
 use crate::Value;
+use crate::lazy::Lazy;
+use std::cmp::Ordering;
+use std::sync::Arc;
 
 use crate::Context;
 lazy_static::lazy_static! {
@@ -12,12 +15,18 @@ lazy_static::lazy_static! {
         ctx.insert_function("rev".to_owned(), &rev);
         ctx.insert_function("filter".to_owned(), &filter);
         ctx.insert_function("sort".to_owned(), &sort);
+        ctx.insert_function("cmp_total".to_owned(), &cmp_total);
         ctx.insert_function("index".to_owned(), &index);
         ctx.insert_function("slice".to_owned(), &slice);
         ctx.insert_function("apply".to_owned(), &apply);
         ctx.insert_function("len".to_owned(), &len);
         ctx.insert_function("chars".to_owned(), &chars);
         ctx.insert_function("range".to_owned(), &range);
+        ctx.insert_function("count_from".to_owned(), &count_from);
+        ctx.insert_function("cycle".to_owned(), &cycle);
+        ctx.insert_function("generate".to_owned(), &generate);
+        ctx.insert_function("take".to_owned(), &take);
+        ctx.insert_function("collect".to_owned(), &collect);
         ctx.insert_function("first".to_owned(), &first);
         ctx.insert_function("repeat".to_owned(), &repeat);
         ctx.insert_function("enumerate".to_owned(), &enumerate);
@@ -36,6 +45,27 @@ lazy_static::lazy_static! {
         ctx.insert_function("bin".to_owned(), &bin);
         ctx.insert_function("ord".to_owned(), &ord);
         ctx.insert_function("chr".to_owned(), &chr);
+        ctx.insert_function("sort_by".to_owned(), &sort_by);
+        ctx.insert_function("sort_with".to_owned(), &sort_with);
+        ctx.insert_function("flatten".to_owned(), &flatten);
+        ctx.insert_function("unique".to_owned(), &unique);
+        ctx.insert_function("group_by".to_owned(), &group_by);
+        ctx.insert_function("partition".to_owned(), &partition);
+        ctx.insert_function("any".to_owned(), &any);
+        ctx.insert_function("all".to_owned(), &all);
+        ctx.insert_function("find".to_owned(), &find);
+        ctx.insert_function("position".to_owned(), &position);
+        ctx.insert_function("contains".to_owned(), &contains);
+        ctx.insert_function("take_while".to_owned(), &take_while);
+        ctx.insert_function("drop_while".to_owned(), &drop_while);
+        ctx.insert_function("scan".to_owned(), &scan);
+        ctx.insert_function("chunks".to_owned(), &chunks);
+        ctx.insert_function("windows".to_owned(), &windows);
+        ctx.insert_function("transpose".to_owned(), &transpose);
+        ctx.insert_function("count".to_owned(), &count);
+        ctx.insert_function("min_by".to_owned(), &min_by);
+        ctx.insert_function("max_by".to_owned(), &max_by);
+        ctx.insert_function("last".to_owned(), &last);
         ctx
     };
 }
@@ -62,6 +92,9 @@ pub fn eval(args: Vec<Value>) -> Result {
 
 pub fn map(args: Vec<Value>) -> Result {
     min_args(args.len(), 1)?;
+    if let Value::Iter(seq) = &args[0] {
+        return Ok(Value::Iter(Arc::new(Lazy::Map{source: seq.clone(), funcs: args[1..].to_vec()})))
+    }
     let first = &args[0];
     let fns = &args[1..];
     if let Value::List(v) = first {
@@ -83,31 +116,23 @@ pub fn map(args: Vec<Value>) -> Result {
 
 pub fn fold(args: Vec<Value>) -> Result {
     bound_args(args.len(), 2, 3)?;
-    if args.len() == 3 {
-        let (mut res, list, func) = (args[0].clone(), &args[1], &args[2]);
-        if let Value::List(l) = list {
-            for v in l {
-                res = func.eval(vec![res, v.clone()])?;
-            }
-            Ok(res)
-        } else {
-            Err(EvalErrorKind::WrongArgType(args[1].clone()).into())
-        }
+    let (init, list, func) = if args.len() == 3 {
+        (Some(args[0].clone()), &args[1], &args[2])
     } else {
-        let (list, func) = (args[0].clone(), &args[1]);
-        if let Value::List(l) = list {
-            if l.len() == 0 {
-                return Ok(Value::Void)
-            }
-            let mut res = l[0].clone();
-            for v in &l[1..] {
-                res = func.eval(vec![res, v.clone()])?;
-            }
-            Ok(res)
-        } else {
-            Err(EvalErrorKind::WrongArgType(args[1].clone()).into())
+        (None, &args[0], &args[1])
+    };
+    let mut iter = finite_iter_arg(list)?;
+    let mut res = match init {
+        Some(x) => x,
+        None => match iter.next() {
+            Some(x) => x?,
+            None => return Ok(Value::Void)
         }
+    };
+    for v in iter {
+        res = func.eval(vec![res, v?])?;
     }
+    Ok(res)
 }
 
 pub fn rev(args: Vec<Value>) -> Result {
@@ -115,6 +140,7 @@ pub fn rev(args: Vec<Value>) -> Result {
     match &args[0] {
         Value::List(l) => Ok(Value::List(l.iter().rev().cloned().collect())),
         Value::Str(s) => Ok(Value::Str(s.chars().rev().collect())),
+        Value::Iter(_) => Ok(Value::List(list_arg(&args[0])?.iter().rev().cloned().collect())),
         _ => Err(EvalErrorKind::WrongArgType(args[0].clone()).into())
     }
 }
@@ -142,16 +168,20 @@ pub fn filter(args: Vec<Value>) -> Result {
             }
             Ok(Value::Str(res))
         },
+        Value::Iter(seq) => Ok(Value::Iter(Arc::new(Lazy::Filter{source: seq.clone(), func: func.clone()}))),
         _ => return Err(EvalErrorKind::WrongArgType(args[0].clone()).into())
     }
 }
 
+/// Sorts a list using the total order [`Value::cmp_total`], or the characters of a string.
+/// The sort is stable.
 pub fn sort(args: Vec<Value>) -> Result {
     bound_args(args.len(), 1, 1)?;
     match &args[0] {
-        Value::List(l) => {
-            let l: Vec<&Value> = sort_inner(l.iter().by_ref().collect());
-            Ok(Value::List(l.iter().map(|&x| x.clone()).collect()))
+        Value::List(_) | Value::Iter(_) => {
+            let mut l = list_arg(&args[0])?.to_vec();
+            l.sort_by(Value::cmp_total);
+            Ok(Value::List(l))
         },
         Value::Str(s) => {
             let mut s2: Vec<char> = s.chars().collect();
@@ -162,31 +192,18 @@ pub fn sort(args: Vec<Value>) -> Result {
     }
 }
 
-pub fn sort_inner(list: Vec<&Value>) -> Vec<&Value> {
-    if list.len() <= 1 {
-        return list
-    }
-    let pivot: &Value = list[0];
-    let mut before: Vec<&Value> = vec![];
-    let mut after: Vec<&Value> = vec![];
-    for v in list.into_iter().skip(1) {
-        if v >= pivot {
-            after.push(v);
-        } else {
-            before.push(v);
-        }
-    }
-    let mut res = sort_inner(before);
-    let after = sort_inner(after);
-    res.push(pivot);
-    for v in after {
-        res.push(v);
-    }
-    res
+/// Compares two values using the total order [`Value::cmp_total`], returning -1, 0, or 1.
+pub fn cmp_total(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    Ok(Value::Integer(match args[0].cmp_total(&args[1]) {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1
+    }))
 }
 
 pub fn index(args: Vec<Value>) -> Result {
-    use std::convert::TryInto;
+    use std::convert::{TryFrom, TryInto};
     bound_args(args.len(), 2, 2)?;
     let list = &args[0];
     let idx = &args[1];
@@ -199,12 +216,23 @@ pub fn index(args: Vec<Value>) -> Result {
             }
         },
         (Value::Str(s), Value::Integer(i)) => {
-            if *i >= 0 && *i < s.len().try_into().unwrap_or(-1) {
-                Ok(Value::Str(s.chars().nth(*i as usize).unwrap().to_string()))
-            } else {
-                Err(EvalErrorKind::ListOutOfBounds(*i).into())
+            match usize::try_from(*i).ok().and_then(|i| s.chars().nth(i)) {
+                Some(c) => Ok(Value::Str(c.to_string())),
+                None => Err(EvalErrorKind::ListOutOfBounds(*i).into())
+            }
+        },
+        (Value::Iter(seq), Value::Integer(i)) => {
+            if let Ok(i) = usize::try_from(*i) {
+                for (j, x) in seq.iter().enumerate() {
+                    crate::limits::step()?;
+                    if j == i {
+                        return x
+                    }
+                }
             }
+            Err(EvalErrorKind::ListOutOfBounds(*i).into())
         },
+        (Value::Iter(_), x) => Err(EvalErrorKind::WrongArgType(x.clone()).into()),
         (Value::List(_), x) => Err(EvalErrorKind::WrongArgType(x.clone()).into()),
         (Value::Str(_), x) => Err(EvalErrorKind::WrongArgType(x.clone()).into()),
         (x,_) => Err(EvalErrorKind::WrongArgType(x.clone()).into())
@@ -213,6 +241,10 @@ pub fn index(args: Vec<Value>) -> Result {
 
 pub fn slice(args: Vec<Value>) -> Result {
     bound_args(args.len(), 3, 3)?;
+    if let Value::Iter(_) = &args[0] {
+        let list = Value::List(list_arg(&args[0])?.to_vec());
+        return slice(vec![list, args[1].clone(), args[2].clone()])
+    }
     let list = &args[0];
     let idx1 = &args[1];
     let idx2 = &args[2];
@@ -240,6 +272,7 @@ pub fn slice(args: Vec<Value>) -> Result {
             }
         },
         (Value::Str(s), Value::Integer(a), Value::Integer(b)) => {
+            let s: Vec<char> = s.chars().collect();
             let len = s.len() as i64;
             let mut a = *a;
             let mut b = *b;
@@ -256,9 +289,9 @@ pub fn slice(args: Vec<Value>) -> Result {
             if a == b {
                 Ok(Value::Str(String::new()))
             } else if a < b {
-                Ok(Value::Str(s[a..b].to_owned()))
+                Ok(Value::Str(s[a..b].iter().collect()))
             } else {
-                Ok(Value::Str(s[b..a].chars().rev().collect::<String>()))
+                Ok(Value::Str(s[b..a].iter().rev().collect()))
             }
         },
         (_, Value::Integer(_), x) => Err(EvalErrorKind::WrongArgType(x.clone()).into()),
@@ -270,12 +303,7 @@ pub fn slice(args: Vec<Value>) -> Result {
 
 pub fn apply(args: Vec<Value>) -> Result {
     bound_args(args.len(), 2, 2)?;
-    let func = &args[0];
-    let list = &args[1];
-    match list {
-        Value::List(l) => func.eval(l.to_vec()),
-        _ => Err(EvalErrorKind::WrongArgType(args[1].clone()).into())
-    }
+    args[0].eval(list_arg(&args[1])?.to_vec())
 }
 
 pub fn len(args: Vec<Value>) -> Result {
@@ -283,7 +311,14 @@ pub fn len(args: Vec<Value>) -> Result {
     if let Value::List(l) = &args[0] {
         Ok(Value::Integer(l.len() as i64))
     } else if let Value::Str(s) = &args[0] {
-        Ok(Value::Integer(s.len() as i64))
+        Ok(Value::Integer(s.chars().count() as i64))
+    } else if let Value::Iter(_) = &args[0] {
+        let mut n = 0;
+        for v in finite_iter_arg(&args[0])? {
+            v?;
+            n += 1;
+        }
+        Ok(Value::Integer(n))
     } else {
         Err(EvalErrorKind::WrongArgType(args[0].clone()).into())
     }
@@ -292,47 +327,87 @@ pub fn len(args: Vec<Value>) -> Result {
 pub fn chars(args: Vec<Value>) -> Result {
     bound_args(args.len(), 1, 1)?;
     match &args[0] {
-        Value::Str(s) => Ok(Value::List(s.chars().map(|x| Value::Str(x.to_string())).collect())),
+        Value::Str(s) => {
+            crate::limits::check_len(s.chars().count())?;
+            Ok(Value::List(s.chars().map(|x| Value::Str(x.to_string())).collect()))
+        },
         _ => Err(EvalErrorKind::WrongArgType(args[0].clone()).into())
     }
 }
 
-/// Return a list of values within a range
-/// range(max) => (0, 1, 2, ..., max)
-/// range(min, max) => (min, min+1, min+2, ..., max)
-/// range(min, max, step) => (min, min+step, min+2*step, ...) until >= max
+/// Return a lazy sequence of integers within a range. The bounds may also be floats with
+/// integer values.
+/// range(max) => (0, 1, 2, ..., max-1)
+/// range(min, max) => (min, min+1, min+2, ..., max-1)
+/// range(min, max, step) => (min, min+step, min+2*step, ...) until >= max (or <= max if step
+/// is negative)
 pub fn range(args: Vec<Value>) -> Result {
     bound_args(args.len(), 1, 3)?;
-    if args.len() == 1 {
-        // 0..max
-        if let Value::Integer(n) = args[0] {
-            Ok(Value::List((0..n).map(|x| Value::Integer(x)).collect()))
-        } else {
-            Err(EvalErrorKind::WrongArgType(args[0].clone()).into())
-        }
-    } else if args.len() == 2 {
-        // min..max
-        match (&args[0], &args[1]) {
-            (Value::Integer(min), Value::Integer(max)) =>
-                Ok(Value::List((*min..*max).map(|x| Value::Integer(x)).collect())),
-            (Value::Integer(_), x) => Err(EvalErrorKind::WrongArgType(x.clone()).into()),
-            (x, _) => Err(EvalErrorKind::WrongArgType(x.clone()).into())
-        }
-    } else {
-        match (&args[0], &args[1], &args[2]) {
-            (Value::Integer(min), Value::Integer(max), Value::Integer(step)) 
-                if *step > 0 => 
-                Ok(Value::List(
-                        (*min..*max).step_by(*step as usize)
-                        .map(|x| Value::Integer(x))
-                        .collect())),
-            (Value::Integer(_), Value::Integer(_), x) => Err(EvalErrorKind::WrongArgType(x.clone()).into()),
-            (Value::Integer(_), x, _) => Err(EvalErrorKind::WrongArgType(x.clone()).into()),
-            (x, _, _) => Err(EvalErrorKind::WrongArgType(x.clone()).into())
-        }
+    let (start, end) = match args.len() {
+        1 => (0, int_arg(&args[0])?),
+        _ => (int_arg(&args[0])?, int_arg(&args[1])?)
+    };
+    let step = match args.get(2) {
+        Some(x) => int_arg(x)?,
+        None => 1
+    };
+    if step == 0 {
+        return Err(EvalErrorKind::WrongArgValue(args[2].clone()).into())
+    }
+    Ok(Value::Iter(Arc::new(Lazy::Range{start, end: Some(end), step})))
+}
+
+fn int_arg(val: &Value) -> std::result::Result<i64, EvalError> {
+    match val {
+        Value::Integer(n) => Ok(*n),
+        Value::Float(f) if f.fract() == 0.0 && f.abs() < 9.2e18 => Ok(*f as i64),
+        Value::Float(_) => Err(EvalErrorKind::WrongArgValue(val.clone()).into()),
+        _ => Err(EvalErrorKind::WrongArgType(val.clone()).into())
+    }
+}
+
+/// Returns the infinite sequence of integers `start, start+step, start+2*step, ...`, with a
+/// step of 1 by default.
+pub fn count_from(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 2)?;
+    let start = int_arg(&args[0])?;
+    let step = match args.get(1) {
+        Some(x) => int_arg(x)?,
+        None => 1
+    };
+    Ok(Value::Iter(Arc::new(Lazy::Range{start, end: None, step})))
+}
+
+/// Returns an infinite sequence that repeats the elements of a list forever.
+pub fn cycle(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    Ok(Value::Iter(Arc::new(Lazy::Cycle(list_arg(&args[0])?.to_vec()))))
+}
+
+/// Returns the infinite sequence `init, f(init), f(f(init)), ...`.
+pub fn generate(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    Ok(Value::Iter(Arc::new(Lazy::Generate{func: args[0].clone(), init: args[1].clone()})))
+}
+
+/// Returns the first `n` elements of a sequence as a sequence, or of a list as a list.
+pub fn take(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    match (&args[0], &args[1]) {
+        (Value::Iter(seq), Value::Integer(n)) if *n >= 0
+            => Ok(Value::Iter(Arc::new(Lazy::Take{source: seq.clone(), count: *n as usize}))),
+        (Value::List(_), _) => first(args),
+        (Value::Iter(_), x) => Err(EvalErrorKind::WrongArgType(x.clone()).into()),
+        (x, _) => Err(EvalErrorKind::WrongArgType(x.clone()).into())
     }
 }
 
+/// Collects a finite sequence into a list. Lists are returned unchanged.
+pub fn collect(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    Ok(Value::List(list_arg(&args[0])?.into_owned()))
+}
+
 pub fn first(args: Vec<Value>) -> Result {
     bound_args(args.len(), 2, 2)?;
     match (&args[0], &args[1]) {
@@ -344,13 +419,13 @@ pub fn first(args: Vec<Value>) -> Result {
             }
         },
         (Value::Str(s), Value::Integer(n)) if *n >= 0 => {
-            if s.len() <= *n as usize {
-                Ok(Value::Str(s.to_owned()))
-            } else {
-                Ok(Value::Str(s[..(*n as usize)].to_owned()))
-            }
+            Ok(Value::Str(s.chars().take(*n as usize).collect()))
         },
-        (Value::List(_), x) | (Value::Str(_), x) => Err(EvalErrorKind::WrongArgType(x.clone()).into()),
+        (Value::Iter(seq), Value::Integer(n)) if *n >= 0 => {
+            Ok(Value::List(Lazy::Take{source: seq.clone(), count: *n as usize}.collect()?))
+        },
+        (Value::List(_), x) | (Value::Str(_), x) | (Value::Iter(_), x)
+            => Err(EvalErrorKind::WrongArgType(x.clone()).into()),
         (x, _) => Err(EvalErrorKind::WrongArgType(x.clone()).into())
     }
 }
@@ -359,10 +434,12 @@ pub fn repeat(args: Vec<Value>) -> Result {
     bound_args(args.len(), 2, 2)?;
     match (&args[0], &args[1]) {
         (Value::List(l), Value::Integer(n)) if *n >= 0 => {
+            crate::limits::check_len(l.len().saturating_mul(*n as usize))?;
             Ok(Value::List(l.iter().cloned().cycle().take(l.len()*(*n as usize)).collect()))
         },
         (Value::Str(s), Value::Integer(n)) if *n >= 0 => {
-            Ok(Value::Str(s.chars().cycle().take(s.len()*(*n as usize)).collect()))
+            crate::limits::check_len(s.chars().count().saturating_mul(*n as usize))?;
+            Ok(Value::Str(s.repeat(*n as usize)))
         },
         (Value::List(_), x) | (Value::Str(_), x) => Err(EvalErrorKind::WrongArgType(x.clone()).into()),
         (x, _) => Err(EvalErrorKind::WrongArgType(x.clone()).into())
@@ -371,6 +448,12 @@ pub fn repeat(args: Vec<Value>) -> Result {
 
 pub fn zip(args: Vec<Value>) -> Result {
     min_args(args.len(), 1)?;
+    if args.iter().any(|x| matches!(x, Value::Iter(_))) {
+        if let Some(x) = args.iter().find(|x| !matches!(x, Value::Iter(_) | Value::List(_))) {
+            return Err(EvalErrorKind::WrongArgType(x.clone()).into())
+        }
+        return Ok(Value::Iter(Arc::new(Lazy::Zip(args))))
+    }
     let mut min_len = usize::MAX;
     let mut lists = vec![];
     for arg in args {
@@ -392,6 +475,10 @@ pub fn zip(args: Vec<Value>) -> Result {
 
 pub fn enumerate(args: Vec<Value>) -> Result {
     bound_args(args.len(), 1, 1)?;
+    if let Value::Iter(_) = &args[0] {
+        let indices = Value::Iter(Arc::new(Lazy::Range{start: 0, end: None, step: 1}));
+        return Ok(Value::Iter(Arc::new(Lazy::Zip(vec![indices, args[0].clone()]))))
+    }
     if let Value::List(l) = &args[0] {
         Ok(Value::List(
                 l.iter().cloned()
@@ -411,6 +498,7 @@ pub fn iter(args: Vec<Value>) -> Result {
     if let Value::Integer(n) = args[2] {
         let mut val = init.clone();
         for _ in 0..n {
+            crate::limits::step()?;
             val = func.eval(vec![val])?;
         }
         Ok(val)
@@ -426,6 +514,7 @@ pub fn iter_while(args: Vec<Value>) -> Result {
     let test = &args[2];
     let mut val = init.clone();
     loop {
+        crate::limits::step()?;
         val = func.eval(vec![val.clone()])?;
         if Value::Bool(false) == test.eval(vec![val.clone()])? {
             break
@@ -441,6 +530,7 @@ pub fn enumiter(args: Vec<Value>) -> Result {
     if let Value::Integer(n) = args[2] {
         let mut val = init.clone();
         for i in 0..n {
+            crate::limits::step()?;
             val = func.eval(vec![Value::Integer(i), val])?;
         }
         Ok(val)
@@ -454,9 +544,11 @@ pub fn seq(args: Vec<Value>) -> Result {
     let func = &args[0];
     let init = &args[1];
     if let Value::Integer(n) = args[2] {
+        crate::limits::check_len(n.max(0) as usize)?;
         let mut val = init.clone();
         let mut res = vec![val.clone()];
         for _ in 0..(n-1) {
+            crate::limits::step()?;
             val = func.eval(vec![val])?;
             res.push(val.clone())
         }
@@ -486,6 +578,7 @@ pub fn fn_loop(args: Vec<Value>) -> Result {
     bound_args(args.len(), 1, 1)?;
     let mut res = Value::Void;
     loop {
+        crate::limits::step()?;
         let a = args[0].eval(vec![])?;
         if a.is_void() {
             break
@@ -606,3 +699,353 @@ pub fn chr(args: Vec<Value>) -> Result {
         Err(EvalErrorKind::WrongArgType(args[0].clone()).into())
     }
 }
+
+fn size_arg(val: &Value) -> std::result::Result<usize, EvalError> {
+    match val {
+        Value::Integer(n) if *n > 0 => Ok(*n as usize),
+        Value::Integer(_) => Err(EvalErrorKind::WrongArgValue(val.clone()).into()),
+        _ => Err(EvalErrorKind::WrongArgType(val.clone()).into())
+    }
+}
+
+fn test(func: &Value, val: &Value) -> std::result::Result<bool, EvalError> {
+    Ok(func.eval(vec![val.clone()])? == Value::Bool(true))
+}
+
+type Compare<'a, T> = dyn FnMut(&T, &T) -> std::result::Result<Ordering, EvalError> + 'a;
+
+/// Stable merge sort with a fallible comparison. Unlike the standard library sorts this never
+/// panics if the comparison is not a total order.
+fn merge_sort<T>(mut list: Vec<T>, cmp: &mut Compare<T>) -> std::result::Result<Vec<T>, EvalError> {
+    if list.len() <= 1 {
+        return Ok(list)
+    }
+    let right = list.split_off(list.len()/2);
+    let left = merge_sort(list, cmp)?;
+    let right = merge_sort(right, cmp)?;
+    let mut res = Vec::with_capacity(left.len() + right.len());
+    let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        if cmp(b, a)? == Ordering::Less {
+            res.push(right.next().unwrap());
+        } else {
+            res.push(left.next().unwrap());
+        }
+    }
+    res.extend(left);
+    res.extend(right);
+    Ok(res)
+}
+
+/// Sorts a list by the result of calling a key function on each element, with keys compared
+/// by [`Value::cmp_total`]. The sort is stable.
+pub fn sort_by(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let mut keyed = vec![];
+    for v in list_arg(&args[0])?.iter() {
+        keyed.push((args[1].eval(vec![v.clone()])?, v.clone()));
+    }
+    keyed.sort_by(|a, b| a.0.cmp_total(&b.0));
+    Ok(Value::List(keyed.into_iter().map(|(_, v)| v).collect()))
+}
+
+/// Sorts a list using a comparison function of two elements, which returns a negative number
+/// if the first is smaller, zero if they are equal, and a positive number otherwise. The sort
+/// is stable.
+pub fn sort_with(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let list = list_arg(&args[0])?.to_vec();
+    let sorted = merge_sort(list, &mut |a, b| {
+        let res = args[1].eval(vec![a.clone(), b.clone()])?;
+        match res.partial_cmp(&Value::Integer(0)) {
+            Some(ord) => Ok(ord),
+            None => Err(EvalErrorKind::WrongArgValue(res).into())
+        }
+    })?;
+    Ok(Value::List(sorted))
+}
+
+/// Flattens nested lists by the given number of levels, one by default.
+pub fn flatten(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 2)?;
+    let depth = match args.get(1) {
+        Some(Value::Integer(n)) if *n >= 0 => *n,
+        Some(x @ Value::Integer(_)) => return Err(EvalErrorKind::WrongArgValue(x.clone()).into()),
+        Some(x) => return Err(EvalErrorKind::WrongArgType(x.clone()).into()),
+        None => 1
+    };
+    fn flatten_inner(list: &[Value], depth: i64, res: &mut Vec<Value>) {
+        for v in list {
+            match v {
+                Value::List(l) if depth > 0 => flatten_inner(l, depth - 1, res),
+                v => res.push(v.clone())
+            }
+        }
+    }
+    let mut res = vec![];
+    flatten_inner(&list_arg(&args[0])?, depth, &mut res);
+    Ok(Value::List(res))
+}
+
+/// Removes duplicate elements, keeping the first occurrence of each.
+pub fn unique(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    let mut res: Vec<Value> = vec![];
+    for v in list_arg(&args[0])?.iter() {
+        if !res.contains(v) {
+            res.push(v.clone());
+        }
+    }
+    Ok(Value::List(res))
+}
+
+/// Groups elements by the result of a key function, returning a list of `(key, elements)`
+/// pairs in order of first appearance.
+pub fn group_by(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let mut groups: Vec<(Value, Vec<Value>)> = vec![];
+    for v in list_arg(&args[0])?.iter() {
+        let key = args[1].eval(vec![v.clone()])?;
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, group)) => group.push(v.clone()),
+            None => groups.push((key, vec![v.clone()]))
+        }
+    }
+    Ok(Value::List(groups.into_iter()
+        .map(|(k, group)| Value::List(vec![k, Value::List(group)]))
+        .collect()))
+}
+
+/// Splits a list into the elements that satisfy a predicate and those that do not.
+pub fn partition(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let (mut yes, mut no) = (vec![], vec![]);
+    for v in list_arg(&args[0])?.iter() {
+        if test(&args[1], v)? {
+            yes.push(v.clone());
+        } else {
+            no.push(v.clone());
+        }
+    }
+    Ok(Value::List(vec![Value::List(yes), Value::List(no)]))
+}
+
+/// Checks if any element satisfies a predicate, or if any element is `true` if no predicate
+/// is given. Stops at the first match.
+pub fn any(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 2)?;
+    for v in iter_arg(&args[0])? {
+        let v = v?;
+        let res = match args.get(1) {
+            Some(func) => test(func, &v)?,
+            None => v == Value::Bool(true)
+        };
+        if res {
+            return Ok(Value::Bool(true))
+        }
+    }
+    Ok(Value::Bool(false))
+}
+
+/// Checks if every element satisfies a predicate, or if every element is `true` if no
+/// predicate is given. Stops at the first failure.
+pub fn all(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 2)?;
+    for v in iter_arg(&args[0])? {
+        let v = v?;
+        let res = match args.get(1) {
+            Some(func) => test(func, &v)?,
+            None => v == Value::Bool(true)
+        };
+        if !res {
+            return Ok(Value::Bool(false))
+        }
+    }
+    Ok(Value::Bool(true))
+}
+
+/// Returns the first element of a list satisfying a predicate, or [`Value::Void`] if there is
+/// none. For strings, see [`super::string::find`].
+pub fn find(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    match &args[0] {
+        Value::List(_) | Value::Iter(_) => {
+            for v in iter_arg(&args[0])? {
+                let v = v?;
+                if test(&args[1], &v)? {
+                    return Ok(v)
+                }
+            }
+            Ok(Value::Void)
+        },
+        Value::Str(_) => super::string::find(args),
+        x => Err(EvalErrorKind::WrongArgType(x.clone()).into())
+    }
+}
+
+/// Returns the index of the first element satisfying a predicate, or [`Value::Void`] if there
+/// is none.
+pub fn position(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    for (i, v) in iter_arg(&args[0])?.enumerate() {
+        if test(&args[1], &v?)? {
+            return Ok(Value::Integer(i as i64))
+        }
+    }
+    Ok(Value::Void)
+}
+
+/// Checks if a list contains a value. For strings, see [`super::string::contains`].
+pub fn contains(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    match &args[0] {
+        Value::List(l) => Ok(Value::Bool(l.contains(&args[1]))),
+        Value::Str(_) => super::string::contains(args),
+        x => Err(EvalErrorKind::WrongArgType(x.clone()).into())
+    }
+}
+
+/// Returns the longest prefix of a list or sequence whose elements satisfy a predicate. The
+/// result is lazy for sequences.
+pub fn take_while(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    if let Value::Iter(seq) = &args[0] {
+        return Ok(Value::Iter(Arc::new(Lazy::TakeWhile{source: seq.clone(), func: args[1].clone()})))
+    }
+    let mut res = vec![];
+    for v in list_arg(&args[0])?.iter() {
+        if !test(&args[1], v)? {
+            break
+        }
+        res.push(v.clone());
+    }
+    Ok(Value::List(res))
+}
+
+/// Removes the longest prefix of a list whose elements satisfy a predicate.
+pub fn drop_while(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let list = list_arg(&args[0])?;
+    for (i, v) in list.iter().enumerate() {
+        if !test(&args[1], v)? {
+            return Ok(Value::List(list[i..].to_vec()))
+        }
+    }
+    Ok(Value::List(vec![]))
+}
+
+/// Like [`fold`], but returns a list of every intermediate result instead of only the last.
+/// `scan(init, list, func)` does not include `init` in the result.
+pub fn scan(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 3)?;
+    let (init, list, func) = if args.len() == 3 {
+        (Some(args[0].clone()), list_arg(&args[1])?, &args[2])
+    } else {
+        (None, list_arg(&args[0])?, &args[1])
+    };
+    let mut iter = list.iter().cloned();
+    let mut acc = match init.or_else(|| iter.next()) {
+        Some(x) => x,
+        None => return Ok(Value::List(vec![]))
+    };
+    let mut res = if args.len() == 3 { vec![] } else { vec![acc.clone()] };
+    for v in iter {
+        acc = func.eval(vec![acc, v])?;
+        res.push(acc.clone());
+    }
+    Ok(Value::List(res))
+}
+
+/// Splits a list into consecutive chunks of the given size. The last chunk may be shorter.
+pub fn chunks(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let size = size_arg(&args[1])?;
+    Ok(Value::List(list_arg(&args[0])?.chunks(size).map(|c| Value::List(c.to_vec())).collect()))
+}
+
+/// Returns every contiguous sublist of the given size, in order.
+pub fn windows(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let size = size_arg(&args[1])?;
+    Ok(Value::List(list_arg(&args[0])?.windows(size).map(|w| Value::List(w.to_vec())).collect()))
+}
+
+/// Transposes a list of lists, which must all have the same length.
+pub fn transpose(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 1, 1)?;
+    let list = list_arg(&args[0])?;
+    let rows = list
+        .iter()
+        .map(list_arg)
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let cols = rows.first().map_or(0, |r| r.len());
+    if rows.iter().any(|r| r.len() != cols) {
+        return Err(EvalErrorKind::WrongArgValue(args[0].clone()).into())
+    }
+    Ok(Value::List((0..cols)
+        .map(|i| Value::List(rows.iter().map(|r| r[i].clone()).collect()))
+        .collect()))
+}
+
+/// Counts the elements satisfying a predicate, or equal to a value if the second argument is
+/// not a function.
+pub fn count(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let mut n = 0;
+    for v in finite_iter_arg(&args[0])? {
+        let v = v?;
+        let res = match &args[1] {
+            func if func.is_function() => test(func, &v)?,
+            x => v == *x
+        };
+        if res {
+            n += 1;
+        }
+    }
+    Ok(Value::Integer(n))
+}
+
+/// Returns the element of a list with the smallest result of a key function, compared by
+/// [`Value::cmp_total`] (the first one in case of ties), or [`Value::Void`] if the list is empty.
+pub fn min_by(args: Vec<Value>) -> Result {
+    extreme_by(args, Ordering::Less)
+}
+
+/// Returns the element of a list with the largest result of a key function, compared by
+/// [`Value::cmp_total`] (the first one in case of ties), or [`Value::Void`] if the list is empty.
+pub fn max_by(args: Vec<Value>) -> Result {
+    extreme_by(args, Ordering::Greater)
+}
+
+fn extreme_by(args: Vec<Value>, want: Ordering) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    let list = list_arg(&args[0])?;
+    let mut best: Option<(Value, &Value)> = None;
+    for v in list.iter() {
+        let key = args[1].eval(vec![v.clone()])?;
+        let better = match &best {
+            Some((best_key, _)) => key.cmp_total(best_key) == want,
+            None => true
+        };
+        if better {
+            best = Some((key, v));
+        }
+    }
+    Ok(best.map_or(Value::Void, |(_, v)| v.clone()))
+}
+
+/// Returns the last `n` elements of a list or characters of a string, see [`first`].
+pub fn last(args: Vec<Value>) -> Result {
+    bound_args(args.len(), 2, 2)?;
+    match (&args[0], &args[1]) {
+        (Value::List(l), Value::Integer(n)) if *n >= 0 => {
+            Ok(Value::List(l[l.len().saturating_sub(*n as usize)..].to_vec()))
+        },
+        (Value::Str(s), Value::Integer(n)) if *n >= 0 => {
+            let count = s.chars().count();
+            Ok(Value::Str(s.chars().skip(count.saturating_sub(*n as usize)).collect()))
+        },
+        (Value::List(_), x) | (Value::Str(_), x) => Err(EvalErrorKind::WrongArgType(x.clone()).into()),
+        (x, _) => Err(EvalErrorKind::WrongArgType(x.clone()).into())
+    }
+}
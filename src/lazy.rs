@@ -0,0 +1,144 @@
+//! Lazy sequences, stored in [`Value::Iter`]. A sequence is a description of how to produce its
+//! elements rather than the elements themselves, so it can be infinite, and iterating over it
+//! again starts from the beginning. Elements are computed as they are needed, so functions
+//! applied by [`Lazy::Map`] and friends may be called more than once for the same element if
+//! the sequence is iterated more than once.
+
+use crate::Value;
+use crate::function::{EvalError, EvalErrorKind};
+use std::sync::Arc;
+
+pub type LazyIter<'a> = Box<dyn Iterator<Item = Result<Value, EvalError>> + 'a>;
+
+pub enum Lazy {
+    /// Integers from `start` in increments of `step`, stopping before `end` (or never)
+    Range{start: i64, end: Option<i64>, step: i64},
+    /// The elements of a list, repeated forever
+    Cycle(Vec<Value>),
+    /// `init`, `func(init)`, `func(func(init))`, ...
+    Generate{func: Value, init: Value},
+    /// Each element with every function applied in order
+    Map{source: Arc<Lazy>, funcs: Vec<Value>},
+    /// The elements for which `func` returns `true`
+    Filter{source: Arc<Lazy>, func: Value},
+    /// Lists of corresponding elements from several lists or sequences, stopping at the
+    /// end of the shortest one
+    Zip(Vec<Value>),
+    /// At most the first `count` elements
+    Take{source: Arc<Lazy>, count: usize},
+    /// Elements until the first one for which `func` does not return `true`
+    TakeWhile{source: Arc<Lazy>, func: Value}
+}
+
+impl Lazy {
+    /// Iterates over the elements of the sequence. Iteration stops after the first error.
+    pub fn iter(&self) -> LazyIter<'_> {
+        match self {
+            Self::Range{start, end, step} => {
+                let (end, step) = (*end, *step);
+                Box::new(std::iter::successors(Some(*start), move |x| x.checked_add(step))
+                    .take_while(move |x| match end {
+                        Some(end) if step > 0 => *x < end,
+                        Some(end) => *x > end,
+                        None => true
+                    })
+                    .map(|x| Ok(Value::Integer(x))))
+            },
+            Self::Cycle(l) => Box::new(l.iter().cloned().cycle().map(Ok)),
+            Self::Generate{func, init} => {
+                let mut last: Option<Value> = None;
+                Box::new(std::iter::from_fn(move || {
+                    let next = match &last {
+                        Some(v) => func.eval(vec![v.clone()]),
+                        None => Ok(init.clone())
+                    };
+                    last = next.as_ref().ok().cloned();
+                    Some(next)
+                }).scan(false, stop_after_error))
+            },
+            Self::Map{source, funcs} => Box::new(source.iter().map(move |x| {
+                let mut x = x?;
+                for f in funcs {
+                    x = f.eval(vec![x])?;
+                }
+                Ok(x)
+            }).scan(false, stop_after_error)),
+            Self::Filter{source, func} => Box::new(source.iter().filter_map(move |x| {
+                let x = match x {
+                    Ok(x) => x,
+                    Err(e) => return Some(Err(e))
+                };
+                match func.eval(vec![x.clone()]) {
+                    Ok(Value::Bool(true)) => Some(Ok(x)),
+                    Ok(_) => None,
+                    Err(e) => Some(Err(e))
+                }
+            }).scan(false, stop_after_error)),
+            Self::Zip(sources) => {
+                let mut iters: Vec<LazyIter> = sources.iter().map(|s| match s {
+                    Value::Iter(seq) => seq.iter(),
+                    Value::List(l) => Box::new(l.iter().cloned().map(Ok)) as LazyIter,
+                    _ => Box::new(std::iter::empty())
+                }).collect();
+                Box::new(std::iter::from_fn(move || {
+                    let mut items = vec![];
+                    for iter in iters.iter_mut() {
+                        match iter.next()? {
+                            Ok(x) => items.push(x),
+                            Err(e) => return Some(Err(e))
+                        }
+                    }
+                    Some(Ok(Value::List(items)))
+                }).scan(false, stop_after_error))
+            },
+            Self::Take{source, count} => Box::new(source.iter().take(*count)),
+            Self::TakeWhile{source, func} => Box::new(source.iter().map(move |x| {
+                let x = x?;
+                match func.eval(vec![x.clone()])? {
+                    Value::Bool(true) => Ok(Some(x)),
+                    _ => Ok(None)
+                }
+            }).map_while(Result::transpose).scan(false, stop_after_error))
+        }
+    }
+
+    /// Whether the sequence is known to never end. Sequences that are not known to be
+    /// infinite may still never end, for example `filter` of an infinite sequence is
+    /// considered infinite but `take_while` of an infinite sequence is not.
+    pub fn is_infinite(&self) -> bool {
+        match self {
+            Self::Range{end, ..} => end.is_none(),
+            Self::Cycle(l) => !l.is_empty(),
+            Self::Generate{..} => true,
+            Self::Map{source, ..} | Self::Filter{source, ..} => source.is_infinite(),
+            Self::Zip(sources) => sources.iter().all(|s| match s {
+                Value::Iter(seq) => seq.is_infinite(),
+                _ => false
+            }),
+            Self::Take{..} | Self::TakeWhile{..} => false
+        }
+    }
+
+    /// Collects the elements of the sequence into a list. Fails if the sequence is infinite.
+    pub fn collect(&self) -> Result<Vec<Value>, EvalError> {
+        if self.is_infinite() {
+            return Err(EvalErrorKind::Other("Cannot collect an infinite sequence".to_owned()).into())
+        }
+        let mut res = vec![];
+        for x in self.iter() {
+            crate::limits::step()?;
+            res.push(x?);
+            crate::limits::check_len(res.len())?;
+        }
+        Ok(res)
+    }
+}
+
+/// Used with [`Iterator::scan`] to end an iterator after it produces an error
+fn stop_after_error(stopped: &mut bool, x: Result<Value, EvalError>) -> Option<Result<Value, EvalError>> {
+    if *stopped {
+        return None
+    }
+    *stopped = x.is_err();
+    Some(x)
+}
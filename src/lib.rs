@@ -5,9 +5,16 @@ pub mod token;
 pub mod tree;
 pub mod ops;
 pub mod function;
+pub mod format;
+pub mod lazy;
+pub mod context;
+pub mod vm;
+pub mod optimizer;
+pub mod limits;
 pub use value::Value;
-pub use tree::Context;
+pub use context::Context;
 pub use tree::Node;
+pub use tree::CompileOptions;
 
 #[derive(Clone, Debug)]
 /// An error type encompassing errors from tokenization, tree creation, and evaluation.
@@ -36,7 +43,7 @@ pub fn eval(expr: &str, ctx: &mut Context) -> Result<Value, Error> {
     }
 }
 
-/// Evaluate an expression with the default context. The context is cloned each time
+/// Evaluate an expression with the default context. The context is copied each time
 /// this function is used, so if mutation of the context is not a concern prefer [`eval`].
 pub fn eval_default(expr: &str) -> Result<Value, Error> {
     let node = compile(expr)?;
@@ -46,14 +53,34 @@ pub fn eval_default(expr: &str) -> Result<Value, Error> {
     }
 }
 
+/// Evaluate an expression with the given context, failing with
+/// [`function::EvalErrorKind::LimitExceeded`] if it exceeds any of the [`limits::Limits`].
+pub fn eval_limited(expr: &str, ctx: &mut Context, limits: limits::Limits) -> Result<Value, Error> {
+    let node = compile(expr)?;
+    limits::with_limits(limits, || node.eval(ctx)).map_err(Error::Eval)
+}
+
 /// Compile an expression to a [`Node`] so it can be evaluated later. This is considerably faster
 /// than evaluating the same expression multiple times.
 pub fn compile(expr: &str) -> Result<Node, Error> {
+    compile_with(expr, &CompileOptions::default())
+}
+
+/// Compile an expression and simplify it with [`optimizer::optimize`], which assumes that the
+/// builtin functions and constants keep their default values. Use [`compile`] to get the tree
+/// as written, for example when debugging.
+pub fn compile_optimized(expr: &str) -> Result<Node, Error> {
+    compile_with(expr, &CompileOptions { optimize: true, ..Default::default() })
+}
+
+/// Compile an expression with the given [`CompileOptions`], for example to run scripts written
+/// for older versions.
+pub fn compile_with(expr: &str, opts: &CompileOptions) -> Result<Node, Error> {
     match token::tokenize(expr) {
         Err(e) => Err(Error::Tokenize(e)),
         Ok(tokens) => {
             //println!("{:?}", tokens);
-            match tree::gen_tree(tokens) {
+            match tree::gen_tree_with(tokens, opts) {
                 Err(e) => Err(Error::Tree(e)),
                 Ok(n) => {
                     //println!("{:?}", n);
@@ -64,6 +91,19 @@ pub fn compile(expr: &str) -> Result<Node, Error> {
     }
 }
 
+/// Compile an expression to bytecode, for expressions that are evaluated many times. See
+/// [`vm`] for how to run the resulting [`vm::Program`].
+pub fn compile_bytecode(expr: &str) -> Result<vm::Program, Error> {
+    Ok(vm::Program::compile(&compile(expr)?))
+}
+
+/// Compile an expression to bytecode as a function of the given parameters, which is called
+/// with their values in order. Variables other than the parameters are looked up in `ctx`. See
+/// [`vm::Bound`].
+pub fn compile_params(expr: &str, params: &[&str], ctx: &Context) -> Result<vm::Bound, Error> {
+    Ok(compile_bytecode(expr)?.bind(ctx, params))
+}
+
 use lazy_static::lazy_static;
 lazy_static! {
     static ref DEFAULT_CONTEXT: Context = {
@@ -86,6 +126,27 @@ lazy_static! {
         for (k, v) in function::complex::CTX_ALL.iter() {
             ctx.insert(k.to_owned(), v.clone());
         }
+        for (k, v) in function::signal::CTX_ALL.iter() {
+            ctx.insert(k.to_owned(), v.clone());
+        }
+        for (k, v) in function::interp::CTX_ALL.iter() {
+            ctx.insert(k.to_owned(), v.clone());
+        }
+        for (k, v) in function::optimize::CTX_ALL.iter() {
+            ctx.insert(k.to_owned(), v.clone());
+        }
+        for (k, v) in function::string::CTX_ALL.iter() {
+            ctx.insert(k.to_owned(), v.clone());
+        }
+        for (k, v) in function::re::CTX_ALL.iter() {
+            ctx.insert(k.to_owned(), v.clone());
+        }
+        for (k, v) in function::functional::CTX_ALL.iter() {
+            ctx.insert(k.to_owned(), v.clone());
+        }
+        for (k, v) in function::refs::CTX_ALL.iter() {
+            ctx.insert(k.to_owned(), v.clone());
+        }
         #[cfg(feature = "random")]
         for (k, v) in function::random::CTX_ALL.iter() {
             ctx.insert(k.to_owned(), v.clone());
@@ -101,14 +162,24 @@ lazy_static! {
     };
 }
 
-/// Create a clone of the default context, including all modules except for [`function::io`].
+/// Create a copy of the default context, including all modules except for [`function::io`].
 pub fn ctx_default() -> Context {
     DEFAULT_CONTEXT.clone()
 }
-/// Create a clone of the full context, including all modules.
+/// Create a copy of the full context, including all modules.
 pub fn ctx_full() -> Context {
     FULL_CONTEXT.clone()
 }
+/// Create a clone of the default context whose random functions draw from a generator seeded
+/// with `seed` instead of the shared process-wide generator. See [`function::random::random_seeded`].
+#[cfg(feature = "random")]
+pub fn ctx_default_seeded(seed: u64) -> Context {
+    let mut ctx = ctx_default();
+    for (k, v) in function::random::random_seeded(seed) {
+        ctx.insert(k, v);
+    }
+    ctx
+}
 /// Create an empty context.
 pub fn ctx_empty() -> Context {
     Context::new()
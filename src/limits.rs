@@ -0,0 +1,142 @@
+//! Limits on the resources an evaluation may use, so that an untrusted expression such as
+//! `loop(:1)` cannot run forever, recurse until the stack overflows, or build a huge list.
+//!
+//! Limits apply to the current thread while [`with_limits`] runs. An evaluation that exceeds
+//! one fails with [`EvalErrorKind::LimitExceeded`]. Without limits, nothing is counted.
+
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+use crate::function::{EvalError, EvalErrorKind};
+
+/// The limits for an evaluation. Every limit is optional, and the default has none.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    /// The maximum number of steps. Evaluating a node of the tree is a step, as is an iteration
+    /// of a looping builtin such as `loop` or `iter_while`, or collecting an element of a
+    /// sequence.
+    pub steps: Option<u64>,
+    /// The maximum number of nested lambda calls. The Rust stack overflows at some depth that
+    /// depends on the size of the thread's stack and on the expression, so set this well below
+    /// it.
+    pub depth: Option<usize>,
+    /// The maximum length of a list built by collecting a sequence, concatenating lists, or by
+    /// builtins such as `repeat` and `seq`. Strings built by `repeat`, padded by `pad_left` and
+    /// `pad_right`, or padded to the width of a format specification are limited to this many
+    /// characters as well.
+    pub list_len: Option<usize>,
+    /// The maximum wall-clock time. It is checked every few steps, so a single slow builtin call
+    /// can run past it.
+    pub time: Option<Duration>,
+}
+
+/// The limit that was exceeded, with its value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Steps(u64), Depth(usize), ListLength(usize), Time(Duration)
+}
+
+impl std::fmt::Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Steps(n) => write!(f, "more than {} steps", n),
+            Self::Depth(n) => write!(f, "call depth over {}", n),
+            Self::ListLength(n) => write!(f, "list longer than {}", n),
+            Self::Time(t) => write!(f, "took longer than {:?}", t),
+        }
+    }
+}
+
+/// How many steps pass between checks of the time limit
+const TIME_CHECK_INTERVAL: u64 = 256;
+
+struct State {
+    limits: Limits,
+    deadline: Option<Instant>,
+    steps: u64,
+    depth: usize
+}
+
+thread_local! {
+    static STATE: RefCell<Option<State>> = const { RefCell::new(None) };
+}
+
+fn exceeded(limit: Limit) -> EvalError {
+    EvalErrorKind::LimitExceeded(limit).into()
+}
+
+/// Run `f` with limits on the evaluations it does on this thread. The limits replace any set by
+/// an enclosing `with_limits` until `f` returns, and the counts start from zero.
+pub fn with_limits<T>(limits: Limits, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<State>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            STATE.with(|s| *s.borrow_mut() = self.0.take());
+        }
+    }
+    let state = State {
+        deadline: limits.time.map(|t| Instant::now() + t),
+        limits,
+        steps: 0,
+        depth: 0
+    };
+    let _restore = Restore(STATE.with(|s| s.borrow_mut().replace(state)));
+    f()
+}
+
+/// Count a step, failing if there are too many or the time is up. Functions that loop for a
+/// number of iterations chosen by the expression should call this on each iteration.
+pub fn step() -> Result<(), EvalError> {
+    STATE.with(|s| match &mut *s.borrow_mut() {
+        None => Ok(()),
+        Some(state) => {
+            state.steps += 1;
+            match state.limits.steps {
+                Some(max) if state.steps > max => return Err(exceeded(Limit::Steps(max))),
+                _ => ()
+            }
+            match (state.deadline, state.limits.time) {
+                (Some(deadline), Some(time)) if state.steps % TIME_CHECK_INTERVAL == 0
+                    && Instant::now() > deadline => Err(exceeded(Limit::Time(time))),
+                _ => Ok(())
+            }
+        }
+    })
+}
+
+/// Fail if a list, or a string of this many characters, is too long.
+pub fn check_len(len: usize) -> Result<(), EvalError> {
+    STATE.with(|s| match &*s.borrow() {
+        Some(State{limits: Limits{list_len: Some(max), ..}, ..}) if len > *max
+            => Err(exceeded(Limit::ListLength(*max))),
+        _ => Ok(())
+    })
+}
+
+/// Decrements the call depth when a call returns, if it was counted
+pub(crate) struct DepthGuard(bool);
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        if self.0 {
+            STATE.with(|s| if let Some(state) = &mut *s.borrow_mut() {
+                state.depth = state.depth.saturating_sub(1);
+            });
+        }
+    }
+}
+
+/// Enter a lambda call, failing if calls are nested too deeply. The depth is decremented when
+/// the returned guard is dropped.
+pub(crate) fn enter() -> Result<DepthGuard, EvalError> {
+    STATE.with(|s| match &mut *s.borrow_mut() {
+        Some(state) => {
+            match state.limits.depth {
+                Some(max) if state.depth >= max => return Err(exceeded(Limit::Depth(max))),
+                _ => ()
+            }
+            state.depth += 1;
+            Ok(DepthGuard(true))
+        },
+        None => Ok(DepthGuard(false))
+    })
+}
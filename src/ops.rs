@@ -4,16 +4,27 @@ use crate::function::{EvalError, EvalTrace};
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
 pub enum BinaryOp {
     Add, Sub, Mul, Div, Mod, Frac, Power,
-    Greater, Less, GreaterEqual, LessEqual, Equal, NotEqual
+    Greater, Less, GreaterEqual, LessEqual, Equal, NotEqual,
+    FloorDiv, BitAnd, BitOr, BitXor, Shl,
+    /// Shift right, or composition of functions: `(f >> g)(x)` is `g(f(x))`
+    Shr,
+    /// `r := v`, replacing the value held by the reference `r`
+    SetRef
 }
 
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
 pub enum UnaryOp {
-    Neg
+    Neg, BitNot
 }
 
 impl BinaryOp {
+    pub fn is_comparison(&self) -> bool {
+        matches!(self, Self::Greater | Self::Less | Self::GreaterEqual | Self::LessEqual
+            | Self::Equal | Self::NotEqual)
+    }
+
     pub fn eval(&self, lhs: Value, rhs: Value) -> Result<Value, EvalError> {
+        use std::cmp::Ordering::*;
         let res = match self {
             Self::Add => lhs + rhs,
             Self::Sub => lhs - rhs,
@@ -22,12 +33,21 @@ impl BinaryOp {
             Self::Mod => lhs % rhs,
             Self::Power => lhs.pow(rhs),
             Self::Frac => lhs.frac(rhs),
-            Self::Greater => Ok(Value::Bool(lhs > rhs)),
-            Self::Less => Ok(Value::Bool(lhs < rhs)),
-            Self::GreaterEqual => Ok(Value::Bool(lhs >= rhs)),
-            Self::LessEqual => Ok(Value::Bool(lhs <= rhs)),
+            Self::Greater => lhs.try_cmp(&rhs).map(|o| Value::Bool(o == Some(Greater))),
+            Self::Less => lhs.try_cmp(&rhs).map(|o| Value::Bool(o == Some(Less))),
+            Self::GreaterEqual => lhs.try_cmp(&rhs).map(|o| Value::Bool(matches!(o, Some(Greater | Equal)))),
+            Self::LessEqual => lhs.try_cmp(&rhs).map(|o| Value::Bool(matches!(o, Some(Less | Equal)))),
             Self::Equal => Ok(Value::Bool(lhs == rhs)),
-            Self::NotEqual => Ok(Value::Bool(lhs != rhs))
+            Self::NotEqual => Ok(Value::Bool(lhs != rhs)),
+            Self::FloorDiv => lhs.div_floor(rhs),
+            Self::BitAnd => lhs & rhs,
+            Self::BitOr => lhs | rhs,
+            Self::BitXor => lhs ^ rhs,
+            Self::Shl if lhs.is_function() => crate::function::functional::then(rhs, lhs),
+            Self::Shl => lhs << rhs,
+            Self::Shr if lhs.is_function() => crate::function::functional::then(lhs, rhs),
+            Self::Shr => lhs >> rhs,
+            Self::SetRef => lhs.set_ref(rhs).map(|_| Value::Void)
         };
         match res {
             Ok(x) => Ok(x),
@@ -39,7 +59,8 @@ impl BinaryOp {
 impl UnaryOp {
     pub fn eval(&self, val: Value) -> Result<Value, EvalError> {
         let res = match self {
-            Self::Neg => -val
+            Self::Neg => -val,
+            Self::BitNot => !val
         };
         match res {
             Ok(x) => Ok(x),
@@ -52,6 +73,7 @@ impl ToString for UnaryOp {
     fn to_string(&self) -> String {
         match self {
             Self::Neg => "-",
+            Self::BitNot => "~",
         }.to_owned()
     }
 }
@@ -71,7 +93,14 @@ impl ToString for BinaryOp {
             Self::GreaterEqual => ">=",
             Self::LessEqual => "<=",
             Self::Equal => "==",
-            Self::NotEqual => "!="
+            Self::NotEqual => "!=",
+            Self::FloorDiv => "\\\\",
+            Self::BitAnd => "&",
+            Self::BitOr => "|",
+            Self::BitXor => "xor",
+            Self::Shl => "<<",
+            Self::Shr => ">>",
+            Self::SetRef => ":="
         }.to_owned()
     }
 }
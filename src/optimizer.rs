@@ -0,0 +1,233 @@
+//! Simplifies [`Node`] trees before they are evaluated, used by [`crate::compile_optimized`].
+//!
+//! The optimizer replaces references to builtin functions and constants with their values,
+//! evaluates operations and calls to pure builtin functions whose arguments are all constant,
+//! picks the branch of a call to a constant boolean such as `(true)(a, b)`, runs lambdas that
+//! are called immediately and do not assign to variables as blocks, and removes block entries that have no effect. Builtins
+//! are only replaced if the expression never assigns to their names and does not use special
+//! identifiers like `$set`, which can assign to any variable.
+
+use std::collections::{HashMap, HashSet};
+use crate::{Value, Context, Node};
+use crate::tree::{Pattern, FormatPart};
+
+lazy_static::lazy_static! {
+    /// The builtins that are replaced by their values. The random functions are left out, since
+    /// [`crate::ctx_default_seeded`] replaces them.
+    static ref BUILTINS: HashMap<String, Value> = {
+        #[allow(unused_mut)]
+        let mut builtins: HashMap<String, Value> = crate::ctx_default().into_iter().collect();
+        #[cfg(feature = "random")]
+        for (k, _) in crate::function::random::CTX_ALL.iter() {
+            builtins.remove(&k);
+        }
+        builtins
+    };
+    /// The builtins that always return the same result for the same arguments and have no side
+    /// effects, so calls to them can be evaluated ahead of time
+    static ref PURE: HashSet<String> = {
+        use crate::function::{ops, trig, num, complex};
+        ops::CTX_ALL.keys().into_iter()
+            .chain(trig::CTX_ALL.keys())
+            .chain(num::CTX_ALL.keys())
+            .chain(complex::CTX_ALL.keys())
+            .collect()
+    };
+}
+
+/// Optimizes a node. The result evaluates to the same value, with the same effects on the
+/// context, as long as the builtins have their default values.
+pub fn optimize(node: Node) -> Node {
+    let mut assigned = HashSet::new();
+    let inline = assigned_names(&node, &mut assigned);
+    Optimizer{assigned, inline}.node(node)
+}
+
+struct Optimizer {
+    /// Names that are assigned to or bound somewhere in the expression
+    assigned: HashSet<String>,
+    /// Whether builtins can be replaced by their values
+    inline: bool
+}
+
+/// Collects the names the expression assigns to or binds. Returns false if it uses special
+/// identifiers.
+fn assigned_names(node: &Node, names: &mut HashSet<String>) -> bool {
+    fn pattern(p: &Pattern, names: &mut HashSet<String>) -> bool {
+        let mut bound = vec![];
+        p.names(&mut bound);
+        names.extend(bound);
+        match p {
+            Pattern::Default(_, default) => assigned_names(default, names),
+            Pattern::List(pats) => pats.iter().all(|p| pattern(p, names)),
+            _ => true
+        }
+    }
+    match node {
+        Node::Assign(name, value) | Node::AssignOp(_, name, value) => {
+            names.insert(name.to_owned());
+            assigned_names(value, names)
+        },
+        Node::AssignPattern(p, value) | Node::Let(p, value) => pattern(p, names) && assigned_names(value, names),
+        Node::FunctionCreate(params, body, _)
+            => params.iter().all(|p| pattern(p, names)) && assigned_names(body, names),
+        Node::Identifier(s) => !s.starts_with('$'),
+        Node::Value(_) => true,
+        Node::BinaryOp(_, lhs, rhs) => assigned_names(lhs, names) && assigned_names(rhs, names),
+        Node::UnaryOp(_, inner) | Node::Spread(inner) | Node::Scope(inner) => assigned_names(inner, names),
+        Node::List(items) | Node::Block(items) => items.iter().all(|n| assigned_names(n, names)),
+        Node::FunctionCall(func, args) => assigned_names(func, names) && args.iter().all(|n| assigned_names(n, names)),
+        Node::Format(parts) => parts.iter().all(|p| match p {
+            FormatPart::Expr(node, _) => assigned_names(node, names),
+            FormatPart::Literal(_) => true
+        }),
+        Node::Compare(first, rest) => assigned_names(first, names) && rest.iter().all(|(_, n)| assigned_names(n, names))
+    }
+}
+
+/// Whether the node may assign to a variable defined outside of it. Assignments in a lambda
+/// body only define variables of the call, so a body that may assign cannot become a block.
+fn may_assign(node: &Node) -> bool {
+    match node {
+        Node::Assign(..) | Node::AssignOp(..) | Node::AssignPattern(..) => true,
+        Node::Let(_, value) => may_assign(value),
+        Node::Identifier(s) => s.starts_with('$'),
+        Node::Value(_) | Node::FunctionCreate(..) => false,
+        Node::BinaryOp(_, lhs, rhs) => may_assign(lhs) || may_assign(rhs),
+        Node::UnaryOp(_, inner) | Node::Spread(inner) | Node::Scope(inner) => may_assign(inner),
+        Node::List(items) | Node::Block(items) => items.iter().any(may_assign),
+        Node::FunctionCall(func, args) => may_assign(func) || args.iter().any(may_assign),
+        Node::Format(parts) => parts.iter().any(|p| matches!(p, FormatPart::Expr(node, _) if may_assign(node))),
+        Node::Compare(first, rest) => may_assign(first) || rest.iter().any(|(_, n)| may_assign(n))
+    }
+}
+
+/// Whether evaluating the node cannot fail or have any effect
+fn is_pure(node: &Node) -> bool {
+    match node {
+        Node::Value(_) | Node::FunctionCreate(..) => true,
+        Node::List(items) => items.iter().all(is_pure),
+        _ => false
+    }
+}
+
+/// Evaluates a node whose operands are all constants, keeping the node if it fails so that
+/// the error happens when it is evaluated
+fn fold(node: Node) -> Node {
+    match node.eval(&mut Context::new()) {
+        Ok(v) => Node::Value(v),
+        Err(_) => node
+    }
+}
+
+impl Optimizer {
+    fn builtin(&self, name: &str) -> Option<&Value> {
+        if self.inline && !self.assigned.contains(name) {
+            BUILTINS.get(name)
+        } else {
+            None
+        }
+    }
+
+    fn boxed(&self, mut node: Box<Node>) -> Box<Node> {
+        *node = self.node(std::mem::replace(&mut node, Node::Value(Value::Void)));
+        node
+    }
+
+    fn node(&self, node: Node) -> Node {
+        let is_value = |n: &Node| matches!(n, Node::Value(_));
+        match node {
+            Node::Identifier(s) => match self.builtin(&s) {
+                Some(v) => Node::Value(v.clone()),
+                None => Node::Identifier(s)
+            },
+            Node::BinaryOp(op, lhs, rhs) => {
+                let (lhs, rhs) = (self.boxed(lhs), self.boxed(rhs));
+                if is_value(&lhs) && is_value(&rhs) {
+                    fold(Node::BinaryOp(op, lhs, rhs))
+                } else {
+                    Node::BinaryOp(op, lhs, rhs)
+                }
+            },
+            Node::UnaryOp(op, inner) => {
+                let inner = self.boxed(inner);
+                if is_value(&inner) {
+                    fold(Node::UnaryOp(op, inner))
+                } else {
+                    Node::UnaryOp(op, inner)
+                }
+            },
+            Node::Compare(first, rest) => {
+                let first = self.boxed(first);
+                let rest: Vec<_> = rest.into_iter().map(|(op, n)| (op, self.node(n))).collect();
+                if is_value(&first) && rest.iter().all(|(_, n)| is_value(n)) {
+                    fold(Node::Compare(first, rest))
+                } else {
+                    Node::Compare(first, rest)
+                }
+            },
+            Node::List(items) => {
+                let items: Vec<_> = items.into_iter().map(|n| self.node(n)).collect();
+                if items.iter().all(is_value) {
+                    fold(Node::List(items))
+                } else {
+                    Node::List(items)
+                }
+            },
+            Node::Block(items) => {
+                let last = items.len().saturating_sub(1);
+                let mut items: Vec<_> = items.into_iter().enumerate()
+                    .map(|(i, n)| (i, self.node(n)))
+                    .filter(|(i, n)| *i == last || !is_pure(n))
+                    .map(|(_, n)| n)
+                    .collect();
+                if items.len() == 1 {
+                    items.pop().unwrap()
+                } else {
+                    Node::Block(items)
+                }
+            },
+            Node::FunctionCall(func, args) => self.call(*func, args),
+            Node::FunctionCreate(params, body, _) => {
+                let body = self.node(*body);
+                let free = crate::tree::free_vars(&params, &body);
+                Node::FunctionCreate(params, Box::new(body), free)
+            },
+            Node::Assign(name, value) => Node::Assign(name, self.boxed(value)),
+            Node::AssignOp(op, name, value) => Node::AssignOp(op, name, self.boxed(value)),
+            Node::AssignPattern(p, value) => Node::AssignPattern(p, self.boxed(value)),
+            Node::Let(p, value) => Node::Let(p, self.boxed(value)),
+            Node::Scope(inner) => Node::Scope(self.boxed(inner)),
+            Node::Spread(inner) => Node::Spread(self.boxed(inner)),
+            Node::Format(parts) => Node::Format(parts.into_iter().map(|p| match p {
+                FormatPart::Expr(node, spec) => FormatPart::Expr(self.boxed(node), spec),
+                p => p
+            }).collect()),
+            node @ Node::Value(_) => node
+        }
+    }
+
+    fn call(&self, func: Node, args: Vec<Node>) -> Node {
+        let pure = matches!(&func, Node::Identifier(s) if self.builtin(s).is_some() && PURE.contains(s));
+        let func = self.node(func);
+        let mut args: Vec<_> = args.into_iter().map(|arg| match arg {
+            // a named argument
+            Node::Assign(name, value) => Node::Assign(name, self.boxed(value)),
+            arg => self.node(arg)
+        }).collect();
+        let plain = args.iter().all(|a| !matches!(a, Node::Assign(..) | Node::Spread(_)));
+        match func {
+            // `(true)(a, b)` is `a`, as long as evaluating `b` has no effect
+            Node::Value(Value::Bool(b)) if plain && args.len() == 2 && is_pure(&args[b as usize]) => {
+                args.swap_remove(!b as usize)
+            },
+            // `(:body)()` is `{body}`, since calling a lambda evaluates its body in a new scope,
+            // unless the body assigns to variables that the block would change
+            Node::FunctionCreate(params, body, _) if params.is_empty() && args.is_empty() && !may_assign(&body) => {
+                Node::Scope(body)
+            },
+            func if pure && plain && args.iter().all(|a| matches!(a, Node::Value(_))) => fold(Node::FunctionCall(Box::new(func), args)),
+            func => Node::FunctionCall(Box::new(func), args)
+        }
+    }
+}
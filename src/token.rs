@@ -1,12 +1,13 @@
 use lazy_static::lazy_static;
 use regex::Regex;
 use crate::ops;
+use crate::format::FormatSpec;
 
 lazy_static! {
     //static ref NEXT_TOKEN: Regex 
     //    = Regex::new(r###"-?^\d+(\.\d*)?i?|-?\.\d+i?|\(|\)|,|;|:|//|\^|<=?|>=?|!=|==|=|[+*-/%]=?|\$?[a-zA-Z_][a-zA-Z0-9_]*|"(?:[^"\\]|\\[\\"nrte0]|\\u\{[0-9a-fA-F]{1,8}\}|\\x[0-9a-fA-F]{2})*""###).unwrap();
     static ref IS_OP: Regex 
-        = Regex::new(r"^\(|\)|,|;|:|//|\^|<=?|>=?|!=|==|=|[+*\-/%]=?").unwrap();
+        = Regex::new(r"^\(|\)|\{|\}|,|;|:=|:|\.\.\.|//|\\\\|\^|\|>|>>|<<|<=?|>=?|!=|==|=|[&|~]|[+*\-/%]=?").unwrap();
     static ref IS_NUMBER: Regex
         = Regex::new(r"^\d+(\.\d*)?i?|\.\d+i?").unwrap();
     static ref IS_IDENT: Regex
@@ -20,13 +21,21 @@ pub enum Token {
     BinaryOp(ops::BinaryOp),
     UnaryOp(ops::UnaryOp),
     Assign, AssignOp(ops::BinaryOp),
-    LParen, RParen,
-    Comma, Semicolon, Colon,
+    LParen, RParen, LBrace, RBrace,
+    Comma, Semicolon, Colon, Ellipsis, Pipe,
+    Let,
     Integer(i64), Float(f64), Imaginary(f64), True, False,
-    Identifier(String), Str(String),
+    Identifier(String), Str(String), FStr(Vec<FStrPart>),
     FunctionCall
 }
 
+/// A piece of an interpolated string literal: either literal text (with escapes already
+/// processed) or the tokens of an embedded expression and its format specification
+#[derive(Clone, Debug, PartialEq)]
+pub enum FStrPart {
+    Literal(String), Expr(Vec<Token>, FormatSpec)
+}
+
 impl Token {
     pub fn is_op(&self) -> bool {
         use Token::*;
@@ -34,9 +43,12 @@ impl Token {
            UnaryOp(_) => true,
            BinaryOp(_) => true,
            Assign | AssignOp(_) => true,
+           Let => true,
            Comma => true,
            Semicolon => true,
            Colon => true,
+           Ellipsis => true,
+           Pipe => true,
            _ => false
         }
     }
@@ -46,21 +58,30 @@ impl Token {
         match self {
             Semicolon => 120,
             Comma => 110,
-            Assign | AssignOp(_) => 100,
+            Let => 105,
+            Assign | AssignOp(_) | BinaryOp(ops::BinaryOp::SetRef) => 100,
+            Pipe => 90,
             BinaryOp(ops::BinaryOp::Greater)
                 | BinaryOp(ops::BinaryOp::Less) 
                 | BinaryOp(ops::BinaryOp::GreaterEqual) 
                 | BinaryOp(ops::BinaryOp::LessEqual) 
                 | BinaryOp(ops::BinaryOp::Equal) 
                 | BinaryOp(ops::BinaryOp::NotEqual) => 80,
+            BinaryOp(ops::BinaryOp::BitOr) => 78,
+            BinaryOp(ops::BinaryOp::BitXor) => 77,
+            BinaryOp(ops::BinaryOp::BitAnd) => 76,
+            BinaryOp(ops::BinaryOp::Shl)
+                | BinaryOp(ops::BinaryOp::Shr) => 75,
             BinaryOp(ops::BinaryOp::Add) 
                 | BinaryOp(ops::BinaryOp::Sub) => 70,
             BinaryOp(ops::BinaryOp::Mul) 
                 | BinaryOp(ops::BinaryOp::Mod) 
                 | BinaryOp(ops::BinaryOp::Div) 
+                | BinaryOp(ops::BinaryOp::FloorDiv)
                 | BinaryOp(ops::BinaryOp::Frac) => 60,
             BinaryOp(ops::BinaryOp::Power) => 40,
-            UnaryOp(ops::UnaryOp::Neg) => 35,
+            Ellipsis => 36,
+            UnaryOp(_) => 35,
             Colon => 30,
             FunctionCall => 20,
             _ => 0
@@ -70,9 +91,10 @@ impl Token {
     pub fn right_assoc(&self) -> bool {
         use Token::*;
         match self {
-           UnaryOp(_) => false,
+           // so that `--x` is `-(-x)`
+           UnaryOp(_) => true,
            BinaryOp(ops::BinaryOp::Power) => true,
-           Assign | AssignOp(_) => true,
+           Assign | AssignOp(_) | BinaryOp(ops::BinaryOp::SetRef) => true,
            Comma => true,
            _ => false
         }
@@ -81,7 +103,21 @@ impl Token {
 
 #[derive(Clone, Debug)]
 pub enum TokenizeError {
-    Unexpected(usize, char), InvalidNumber(usize, String), InvalidCodepoint(u32)
+    Unexpected(usize, char), InvalidNumber(usize, String), InvalidCodepoint(u32),
+    UnclosedFStr(usize), InvalidFormatSpec(usize, String)
+}
+
+impl TokenizeError {
+    /// Shifts the position of the error, for errors in expressions embedded in a larger one
+    fn offset(self, n: usize) -> Self {
+        match self {
+            Self::Unexpected(i, c) => Self::Unexpected(i + n, c),
+            Self::InvalidNumber(i, s) => Self::InvalidNumber(i + n, s),
+            Self::UnclosedFStr(i) => Self::UnclosedFStr(i + n),
+            Self::InvalidFormatSpec(i, s) => Self::InvalidFormatSpec(i + n, s),
+            x => x
+        }
+    }
 }
 
 impl std::fmt::Display for TokenizeError {
@@ -92,7 +128,11 @@ impl std::fmt::Display for TokenizeError {
             Self::InvalidNumber(n, s)
                 => write!(f, "Numerical literal '{}' at {} could not be parsed as a number", s, n),
             Self::InvalidCodepoint(n)
-                => write!(f, "{:#x} is not a valid Unicode codepoint", n)
+                => write!(f, "{:#x} is not a valid Unicode codepoint", n),
+            Self::UnclosedFStr(n)
+                => write!(f, "Interpolated string at position {} is not closed", n),
+            Self::InvalidFormatSpec(n, s)
+                => write!(f, "Invalid format specification '{}' at position {}", s, n)
         }
     }
 }
@@ -106,6 +146,13 @@ pub fn tokenize(s: &str) -> Result<Vec<Token>, TokenizeError> {
         s = s.trim_start();
         idx += s_len_before - s.len();
         let end;
+        if s.starts_with("f\"") {
+            let (token, end) = tokenize_fstr(s, idx)?;
+            tokens.push(token);
+            idx += end;
+            s = &s[end..];
+            continue
+        }
         if let Some(next) = IS_IDENT.find(s) {
             if next.start() == 0 {
                 let end = next.end();
@@ -116,6 +163,10 @@ pub fn tokenize(s: &str) -> Result<Vec<Token>, TokenizeError> {
                     tokens.push(Token::True);
                 } else if st == "false" {
                     tokens.push(Token::False);
+                } else if st == "let" {
+                    tokens.push(Token::Let);
+                } else if st == "xor" {
+                    tokens.push(Token::BinaryOp(ops::BinaryOp::BitXor));
                 } else {
                     tokens.push(Token::Identifier(st.to_owned()))
                 }
@@ -175,6 +226,13 @@ pub fn tokenize(s: &str) -> Result<Vec<Token>, TokenizeError> {
                     "<" => Token::BinaryOp(ops::BinaryOp::Less),
                     ">=" => Token::BinaryOp(ops::BinaryOp::GreaterEqual),
                     "<=" => Token::BinaryOp(ops::BinaryOp::LessEqual),
+                    ">>" => Token::BinaryOp(ops::BinaryOp::Shr),
+                    "<<" => Token::BinaryOp(ops::BinaryOp::Shl),
+                    "\\\\" => Token::BinaryOp(ops::BinaryOp::FloorDiv),
+                    "&" => Token::BinaryOp(ops::BinaryOp::BitAnd),
+                    "|" => Token::BinaryOp(ops::BinaryOp::BitOr),
+                    "~" => Token::UnaryOp(ops::UnaryOp::BitNot),
+                    "|>" => Token::Pipe,
                     "=" => Token::Assign,
                     "+=" => Token::AssignOp(ops::BinaryOp::Add),
                     "-=" => Token::AssignOp(ops::BinaryOp::Sub),
@@ -183,9 +241,13 @@ pub fn tokenize(s: &str) -> Result<Vec<Token>, TokenizeError> {
                     "%=" => Token::AssignOp(ops::BinaryOp::Mod),
                     "(" => Token::LParen,
                     ")" => Token::RParen,
+                    "{" => Token::LBrace,
+                    "}" => Token::RBrace,
                     "," => Token::Comma,
                     ";" => Token::Semicolon,
+                    ":=" => Token::BinaryOp(ops::BinaryOp::SetRef),
                     ":" => Token::Colon,
+                    "..." => Token::Ellipsis,
                     _ => unreachable!()
                 };
                 tokens.push(token);
@@ -199,6 +261,102 @@ pub fn tokenize(s: &str) -> Result<Vec<Token>, TokenizeError> {
     Ok(tokens)
 }
 
+/// Tokenizes the interpolated string literal at the start of `s`, returning the token and its
+/// length in bytes. `idx` is the position of `s` in the whole expression.
+fn tokenize_fstr(s: &str, idx: usize) -> Result<(Token, usize), TokenizeError> {
+    let mut parts = vec![];
+    let mut literal = String::new();
+    // skip the leading `f"`
+    let mut chars = s.char_indices().skip(2).peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => {
+                push_literal(&mut parts, &literal, idx)?;
+                return Ok((Token::FStr(parts), i + 1))
+            },
+            '\\' => {
+                literal.push(c);
+                if let Some((_, esc)) = chars.next() {
+                    literal.push(esc);
+                    // the braces of `\u{...}` are part of the escape
+                    if esc == 'u' {
+                        for (_, c) in chars.by_ref() {
+                            literal.push(c);
+                            if c == '}' {
+                                break
+                            }
+                        }
+                    }
+                }
+            },
+            '{' | '}' if chars.peek().map(|x| x.1) == Some(c) => {
+                chars.next();
+                literal.push(c);
+            },
+            '}' => return Err(TokenizeError::Unexpected(idx + i, c)),
+            '{' => {
+                push_literal(&mut parts, &literal, idx)?;
+                literal.clear();
+                // find the closing brace and the colon starting the format specification,
+                // skipping over nested parentheses and string literals
+                let start = i + 1;
+                let (mut depth, mut in_str) = (0, false);
+                let (mut colon, mut end) = (None, None);
+                while let Some((j, c)) = chars.next() {
+                    if in_str {
+                        match c {
+                            '\\' => { chars.next(); },
+                            '"' => in_str = false,
+                            _ => ()
+                        }
+                        continue
+                    }
+                    match c {
+                        '"' => in_str = true,
+                        '(' | '{' => depth += 1,
+                        ')' | '}' if depth > 0 => depth -= 1,
+                        '}' => {
+                            end = Some(j);
+                            break
+                        },
+                        ':' if depth == 0 && colon.is_none() => colon = Some(j),
+                        _ => ()
+                    }
+                }
+                let end = end.ok_or(TokenizeError::UnclosedFStr(idx))?;
+                let expr = &s[start..colon.unwrap_or(end)];
+                if expr.trim().is_empty() {
+                    return Err(TokenizeError::Unexpected(idx + i, c))
+                }
+                let spec_str = colon.map_or("", |c| &s[c+1..end]);
+                let spec = match FormatSpec::parse(spec_str) {
+                    Some(spec) => spec,
+                    None => return Err(TokenizeError::InvalidFormatSpec(idx + start, spec_str.to_owned()))
+                };
+                let tokens = tokenize(expr).map_err(|e| e.offset(idx + start))?;
+                parts.push(FStrPart::Expr(tokens, spec));
+            },
+            c => literal.push(c)
+        }
+    }
+    Err(TokenizeError::UnclosedFStr(idx))
+}
+
+/// Processes the escapes in a piece of literal text from an interpolated string
+fn push_literal(parts: &mut Vec<FStrPart>, literal: &str, idx: usize) -> Result<(), TokenizeError> {
+    if literal.is_empty() {
+        return Ok(())
+    }
+    let quoted = format!("\"{}\"", literal);
+    match IS_STR.find(&quoted) {
+        Some(m) if m.end() == quoted.len() => {
+            parts.push(FStrPart::Literal(parse_str(&quoted)?));
+            Ok(())
+        },
+        _ => Err(TokenizeError::Unexpected(idx, '\\'))
+    }
+}
+
 fn parse_str(raw_str: &str) -> Result<String, TokenizeError> {
     // unreachable!(): regex already checked that this is impossible
     let raw_str = &raw_str[1..(raw_str.len()-1)];
@@ -2,12 +2,14 @@ use crate::Value;
 use crate::ops::*;
 use crate::token::*;
 use crate::function::{self, EvalError, EvalErrorKind, EvalTrace};
+use crate::format::FormatSpec;
 
-pub type Context = std::collections::HashMap<String, Value>;
+pub use crate::context::Context;
 
 #[derive(Clone, Debug)]
 pub enum TreeError {
-    NoLParen, NoRParen, TokenNoArgs(Token), AssignLeftInvalid, ColonLeftNotIdentifier(Node), NoOperator(Token)
+    NoLParen, NoRParen, NoLBrace, NoRBrace, TokenNoArgs(Token), AssignLeftInvalid, ColonLeftNotIdentifier(Node),
+    NoOperator(Token), LetNotAssign, MissingOperator
 }
 
 impl std::fmt::Display for TreeError {
@@ -15,10 +17,14 @@ impl std::fmt::Display for TreeError {
         match self {
             Self::NoLParen => write!(f, "Unmatched closing parenthesis ')'"),
             Self::NoRParen => write!(f, "Unmatched opening parenthesis '('"),
+            Self::NoLBrace => write!(f, "Unmatched closing brace '}}'"),
+            Self::NoRBrace => write!(f, "Unmatched opening brace '{{'"),
             Self::TokenNoArgs(t) => write!(f, "Token {:?} is missing one or both arguments", t),
-            Self::AssignLeftInvalid => write!(f, "Can only assign to an identifier"),
-            Self::ColonLeftNotIdentifier(_) => write!(f, "Left-hand side of colon must be an identifier or a list of identifiers"),
-            Self::NoOperator(t) => write!(f, "Token {:?} has no corresponding operator", t)
+            Self::AssignLeftInvalid => write!(f, "Can only assign to an identifier or a list pattern"),
+            Self::ColonLeftNotIdentifier(_) => write!(f, "Left-hand side of colon must be an identifier or a list of patterns"),
+            Self::NoOperator(t) => write!(f, "Token {:?} has no corresponding operator", t),
+            Self::LetNotAssign => write!(f, "'let' must be followed by an assignment"),
+            Self::MissingOperator => write!(f, "Missing operator between two groups")
         }
     }
 }
@@ -28,32 +34,410 @@ pub enum Node {
     BinaryOp(BinaryOp, Box<Node>, Box<Node>), 
     UnaryOp(UnaryOp, Box<Node>),
     Assign(String, Box<Node>),
+    AssignPattern(Pattern, Box<Node>),
     AssignOp(BinaryOp, String, Box<Node>),
     FunctionCall(Box<Node>, Vec<Node>),
-    FunctionCreate(Vec<String>, Box<Node>),
+    /// A lambda's parameters and body, and the variables it uses from outside, which are
+    /// `None` if the body refers to variables by name at runtime (e.g. with `$get`)
+    FunctionCreate(Vec<Pattern>, Box<Node>, Option<Vec<String>>),
     Value(Value), Identifier(String),
-    List(Vec<Node>), Block(Vec<Node>)
+    List(Vec<Node>), Block(Vec<Node>),
+    Format(Vec<FormatPart>),
+    /// `...x`, only valid inside a pattern or as a function argument
+    Spread(Box<Node>),
+    /// A chain of two or more comparisons such as `a < b <= c`, which is true if every
+    /// comparison is. Each operand is evaluated at most once, from left to right, stopping at
+    /// the first false comparison.
+    Compare(Box<Node>, Vec<(BinaryOp, Node)>),
+    /// `let pattern = value`, defining new variables in the innermost scope even if variables
+    /// with the same names exist in an outer one
+    Let(Pattern, Box<Node>),
+    /// `{ ... }`, evaluated in a new scope whose variables are discarded afterwards
+    Scope(Box<Node>)
+}
+
+/// The target of a destructuring assignment or a lambda parameter
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Ident(String),
+    /// Matches a list with one element for each pattern, or more if it contains a rest pattern
+    List(Vec<Pattern>),
+    /// `...name` inside a list pattern, matching all remaining elements as a list
+    Rest(String),
+    /// `name=default` inside a list pattern, evaluating `default` if there is no element for it
+    Default(String, Box<Node>)
+}
+
+impl Pattern {
+    /// Converts the left-hand side of an assignment. A rest pattern is not allowed at the top
+    /// level or more than once in the same list.
+    pub fn from_node(node: &Node) -> Option<Self> {
+        match node {
+            Node::Identifier(s) => Some(Self::Ident(s.to_owned())),
+            Node::List(items) => Some(Self::List(Self::from_nodes(items)?)),
+            _ => None
+        }
+    }
+
+    /// Converts the elements of a list pattern, or a lambda parameter list. Patterns with
+    /// defaults must come after all other patterns except a rest pattern, and patterns after the
+    /// rest pattern cannot have defaults.
+    pub fn from_nodes(nodes: &[Node]) -> Option<Vec<Self>> {
+        let mut res = vec![];
+        let (mut seen_default, mut seen_rest) = (false, false);
+        for node in nodes {
+            let pat = match node {
+                Node::Spread(inner) => match &**inner {
+                    Node::Identifier(s) if !seen_rest => Self::Rest(s.to_owned()),
+                    _ => return None
+                },
+                Node::Assign(name, default) if !seen_rest => Self::Default(name.to_owned(), default.clone()),
+                x => Self::from_node(x)?
+            };
+            match pat {
+                Self::Rest(_) => seen_rest = true,
+                Self::Default(..) => seen_default = true,
+                _ if seen_default && !seen_rest => return None,
+                _ => ()
+            }
+            res.push(pat);
+        }
+        Some(res)
+    }
+
+    /// Binds the names in the pattern to the matching parts of a value, assigning to existing
+    /// variables where they are defined
+    pub fn bind(&self, value: Value, ctx: &mut Context) -> Result<(), EvalError> {
+        self.bind_with(value, ctx, false)
+    }
+
+    /// Binds the names in the pattern to the matching parts of a value, as new variables in the
+    /// innermost frame of the context
+    pub fn declare(&self, value: Value, ctx: &mut Context) -> Result<(), EvalError> {
+        self.bind_with(value, ctx, true)
+    }
+
+    fn bind_with(&self, value: Value, ctx: &mut Context, declare: bool) -> Result<(), EvalError> {
+        match self {
+            Self::Ident(name) | Self::Rest(name) | Self::Default(name, _) => {
+                check_reserved(name)?;
+                if declare {
+                    ctx.declare(name.to_owned(), value);
+                } else {
+                    ctx.insert(name.to_owned(), value);
+                }
+                Ok(())
+            },
+            Self::List(pats) => {
+                let values = match function::list_arg(&value) {
+                    Ok(l) => l.into_owned(),
+                    Err(_) => return Err(EvalErrorKind::PatternMismatch(value).into())
+                };
+                let (min, max) = Self::arity(pats);
+                if values.len() < min || max.is_some_and(|max| values.len() > max) {
+                    return Err(EvalErrorKind::PatternMismatch(value).into())
+                }
+                Self::bind_all(pats, values, ctx, declare)
+            }
+        }
+    }
+
+    /// Binds the arguments of a lambda to its parameters. Named arguments are bound first, to
+    /// the parameters with those names, and the positional arguments fill the remaining
+    /// parameters in order. Fails if the number of arguments is wrong, with the signature of
+    /// the lambda in the trace, or if a named argument is given twice or names a parameter that
+    /// comes before the last positional argument. The parameters are declared in the innermost
+    /// frame.
+    pub fn bind_args(params: &[Pattern], args: Vec<Value>, named: Vec<(String, Value)>, ctx: &mut Context)
+            -> Result<(), EvalError> {
+        let position = |pats: &[Pattern], name: &str| pats.iter().position(|p| match p {
+            Self::Ident(s) | Self::Default(s, _) => s == name,
+            _ => false
+        });
+        let mut remaining = std::borrow::Cow::Borrowed(params);
+        for (name, value) in named {
+            let positional = position(params, &name).is_some_and(|i| i < args.len()
+                && !params[..i].iter().any(|p| matches!(p, Self::Rest(_))));
+            match position(&remaining, &name) {
+                Some(_) if positional => return Err(EvalErrorKind::DuplicateArg(name).into()),
+                Some(i) => remaining.to_mut().remove(i).declare(value, ctx)?,
+                None if position(params, &name).is_some()
+                    => return Err(EvalErrorKind::DuplicateArg(name).into()),
+                None => return Err(EvalErrorKind::NamedArg(name).into())
+            }
+        }
+        let (min, max) = Self::arity(&remaining);
+        let res = match max {
+            Some(max) => function::bound_args(args.len(), min, max),
+            None => function::min_args(args.len(), min)
+        };
+        if let Err(e) = res {
+            return Err(EvalError{kind: e.kind, trace: EvalTrace::Function(Self::signature(params))})
+        }
+        Self::bind_all(&remaining, args, ctx, true)
+    }
+
+    /// Appends the names bound by the pattern
+    pub fn names(&self, out: &mut Vec<String>) {
+        match self {
+            Self::Ident(name) | Self::Rest(name) | Self::Default(name, _) => out.push(name.to_owned()),
+            Self::List(pats) => pats.iter().for_each(|p| p.names(out))
+        }
+    }
+
+    /// The parameter list of a lambda as it would be written, e.g. `(x, y=1, ...rest)`
+    pub fn signature(params: &[Pattern]) -> String {
+        format!("({})", params.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", "))
+    }
+
+    /// The minimum and maximum number of elements matched by a list of patterns
+    pub fn arity(pats: &[Pattern]) -> (usize, Option<usize>) {
+        let required = pats.iter().filter(|p| !matches!(p, Self::Rest(_) | Self::Default(..))).count();
+        if pats.iter().any(|p| matches!(p, Self::Rest(_))) {
+            (required, None)
+        } else {
+            (required, Some(pats.len()))
+        }
+    }
+
+    /// Binds values to a list of patterns, assuming the number of values is within its arity
+    fn bind_all(pats: &[Pattern], mut values: Vec<Value>, ctx: &mut Context, declare: bool)
+            -> Result<(), EvalError> {
+        let r = pats.iter().position(|p| matches!(p, Self::Rest(_)));
+        let (prefix, suffix) = match r {
+            Some(r) => (&pats[..r], &pats[r+1..]),
+            None => (pats, &pats[pats.len()..])
+        };
+        let tail = values.split_off(values.len() - suffix.len());
+        let rest = values.split_off(values.len().min(prefix.len()));
+        let given = values.len();
+        for (p, v) in prefix.iter().zip(values) {
+            p.bind_with(v, ctx, declare)?;
+        }
+        for p in &prefix[given..] {
+            if let Self::Default(_, default) = p {
+                let v = default.eval(ctx)?;
+                p.bind_with(v, ctx, declare)?;
+            }
+        }
+        if let Some(r) = r {
+            pats[r].bind_with(Value::List(rest), ctx, declare)?;
+        }
+        for (p, v) in suffix.iter().zip(tail) {
+            p.bind_with(v, ctx, declare)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Ident(s) => write!(f, "{}", s),
+            Self::List(l) if l.len() == 1 => write!(f, "({},)", l[0]),
+            Self::List(l) => write!(f, "{}", Self::signature(l)),
+            Self::Rest(s) => write!(f, "...{}", s),
+            Self::Default(s, node) => write!(f, "{}={}", s, node)
+        }
+    }
+}
+
+/// The variables used by a lambda that are not its parameters or defined in it with `let`, in
+/// order of first use. Variables that are assigned to count as used, since they may refer to an
+/// outer variable. Returns `None` if the lambda uses special identifiers, which can access any
+/// variable.
+pub(crate) fn free_vars(params: &[Pattern], body: &Node) -> Option<Vec<String>> {
+    let (mut bound, mut free) = (vec![], vec![]);
+    params_free_vars(params, &mut bound, &mut free)?;
+    collect_free_vars(body, &mut bound, &mut free)?;
+    Some(free)
+}
+
+fn use_var(name: &str, bound: &[String], free: &mut Vec<String>) {
+    if !bound.iter().any(|b| b == name) && !free.iter().any(|f| f == name) {
+        free.push(name.to_owned());
+    }
+}
+
+fn params_free_vars(params: &[Pattern], bound: &mut Vec<String>, free: &mut Vec<String>) -> Option<()> {
+    for param in params {
+        pattern_free_vars(param, bound, free)?;
+        param.names(bound);
+    }
+    Some(())
+}
+
+/// Collects the variables used by the default values in a pattern
+fn pattern_free_vars(pattern: &Pattern, bound: &mut Vec<String>, free: &mut Vec<String>) -> Option<()> {
+    match pattern {
+        Pattern::Default(_, default) => collect_free_vars(default, bound, free),
+        Pattern::List(pats) => pats.iter().try_for_each(|p| pattern_free_vars(p, bound, free)),
+        _ => Some(())
+    }
+}
+
+fn collect_free_vars(node: &Node, bound: &mut Vec<String>, free: &mut Vec<String>) -> Option<()> {
+    match node {
+        Node::Assign(name, value) | Node::AssignOp(_, name, value) => {
+            collect_free_vars(value, bound, free)?;
+            use_var(name, bound, free);
+        },
+        Node::AssignPattern(pattern, value) => {
+            collect_free_vars(value, bound, free)?;
+            pattern_free_vars(pattern, bound, free)?;
+            let mut names = vec![];
+            pattern.names(&mut names);
+            names.iter().for_each(|n| use_var(n, bound, free));
+        },
+        Node::Let(pattern, value) => {
+            collect_free_vars(value, bound, free)?;
+            pattern_free_vars(pattern, bound, free)?;
+            pattern.names(bound);
+        },
+        Node::Identifier(s) => if s.starts_with('$') {
+            return None
+        } else {
+            use_var(s, bound, free);
+        },
+        Node::Value(_) => (),
+        Node::BinaryOp(_, lhs, rhs) => {
+            collect_free_vars(lhs, bound, free)?;
+            collect_free_vars(rhs, bound, free)?;
+        },
+        Node::UnaryOp(_, inner) | Node::Spread(inner) => collect_free_vars(inner, bound, free)?,
+        Node::List(items) | Node::Block(items) => {
+            items.iter().try_for_each(|n| collect_free_vars(n, bound, free))?;
+        },
+        Node::Scope(inner) => {
+            let len = bound.len();
+            collect_free_vars(inner, bound, free)?;
+            bound.truncate(len);
+        },
+        Node::FunctionCall(func, args) => {
+            // `$catch` only evaluates its arguments, unlike the other special functions
+            if !matches!(&**func, Node::Identifier(s) if s == "$catch") {
+                collect_free_vars(func, bound, free)?;
+            }
+            for arg in args {
+                match arg {
+                    Node::Assign(_, value) => collect_free_vars(value, bound, free)?,
+                    _ => collect_free_vars(arg, bound, free)?
+                }
+            }
+        },
+        Node::FunctionCreate(_, _, inner) => {
+            inner.as_ref()?.iter().for_each(|n| use_var(n, bound, free));
+        },
+        Node::Format(parts) => for part in parts {
+            if let FormatPart::Expr(node, _) = part {
+                collect_free_vars(node, bound, free)?;
+            }
+        },
+        Node::Compare(first, rest) => {
+            collect_free_vars(first, bound, free)?;
+            rest.iter().try_for_each(|(_, n)| collect_free_vars(n, bound, free))?;
+        }
+    }
+    Some(())
+}
+
+pub(crate) fn check_reserved(name: &str) -> Result<(), EvalError> {
+    if name == "true" || name == "false" || name.starts_with('$') {
+        Err(EvalErrorKind::IdentifierReserved(name.to_owned()).into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Writes the expression back as source code, with every operation parenthesized
+impl std::fmt::Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fn join(nodes: &[Node], sep: &str) -> String {
+            nodes.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(sep)
+        }
+        match self {
+            Self::BinaryOp(op, lhs, rhs) => write!(f, "({} {} {})", lhs, op.to_string(), rhs),
+            Self::UnaryOp(op, rhs) => write!(f, "{}{}", op.to_string(), rhs),
+            Self::Assign(name, value) => write!(f, "{} = {}", name, value),
+            Self::AssignPattern(pattern, value) => write!(f, "{} = {}", pattern, value),
+            Self::AssignOp(op, name, value) => write!(f, "{} {}= {}", name, op.to_string(), value),
+            Self::FunctionCall(func, args) => write!(f, "{}({})", func, join(args, ", ")),
+            Self::FunctionCreate(params, body, _) => write!(f, "({}:{})", Pattern::signature(params), body),
+            Self::Value(v) => write!(f, "{:?}", v),
+            Self::Identifier(s) => write!(f, "{}", s),
+            Self::List(l) if l.len() == 1 => write!(f, "({},)", l[0]),
+            Self::List(l) => write!(f, "({})", join(l, ", ")),
+            Self::Block(l) => write!(f, "({})", join(l, "; ")),
+            Self::Format(parts) => {
+                write!(f, "f\"")?;
+                for part in parts {
+                    match part {
+                        FormatPart::Literal(s) => {
+                            let s = format!("{:?}", s).replace('{', "{{").replace('}', "}}");
+                            write!(f, "{}", &s[1..s.len()-1])?
+                        },
+                        FormatPart::Expr(node, _) => write!(f, "{{{}}}", node)?
+                    }
+                }
+                write!(f, "\"")
+            },
+            Self::Spread(node) => write!(f, "...{}", node),
+            Self::Compare(first, rest) => {
+                write!(f, "({}", first)?;
+                for (op, node) in rest {
+                    write!(f, " {} {}", op.to_string(), node)?;
+                }
+                write!(f, ")")
+            },
+            Self::Let(pattern, value) => write!(f, "let {} = {}", pattern, value),
+            Self::Scope(node) => write!(f, "{{{}}}", node)
+        }
+    }
+}
+
+/// A piece of an interpolated string: literal text, or an expression and how to format it
+#[derive(Clone, Debug)]
+pub enum FormatPart {
+    Literal(String), Expr(Box<Node>, FormatSpec)
 }
 
 impl Node {
     pub fn eval(&self, ctx: &mut Context) -> Result<Value, EvalError> {
+        let _display = crate::format::enter(ctx.display_cell());
+        self.eval_node(ctx)
+    }
+
+    /// Evaluates a node as part of evaluating a larger tree in the same context
+    fn eval_node(&self, ctx: &mut Context) -> Result<Value, EvalError> {
+        crate::limits::step()?;
         match self {
             Self::Assign(name, value) => {
-                if name == "true" || name == "false" || name.chars().nth(0) == Some('$') {
-                    return Err(EvalErrorKind::IdentifierReserved(name.to_owned()).into())
-                }
-                let result = value.eval(ctx)?;
+                check_reserved(name)?;
+                let result = value.eval_node(ctx)?;
                 ctx.insert(name.to_owned(), result);
                 Ok(Value::Void)
             },
+            Self::AssignPattern(pattern, value) => {
+                let result = value.eval_node(ctx)?;
+                pattern.bind(result, ctx)?;
+                Ok(Value::Void)
+            },
+            Self::Let(pattern, value) => {
+                let result = value.eval_node(ctx)?;
+                pattern.declare(result, ctx)?;
+                Ok(Value::Void)
+            },
+            Self::Scope(inner) => {
+                ctx.push_frame();
+                let result = inner.eval_node(ctx);
+                ctx.pop_frame();
+                result
+            },
             Self::AssignOp(op, name, value) => {
-                if name == "true" || name == "false" || name.chars().nth(0) == Some('$') {
-                    return Err(EvalErrorKind::IdentifierReserved(name.to_owned()).into())
-                }
+                check_reserved(name)?;
                 let name = name.to_owned();
-                if ctx.contains_key(&name) {
-                    let prev = ctx[&name].clone();
-                    let result = value.eval(ctx)?;
+                if let Some(prev) = ctx.get(&name) {
+                    let result = value.eval_node(ctx)?;
                     let result = op.eval(prev, result)?;
                     ctx.insert(name.to_owned(), result);
                     Ok(Value::Void)
@@ -62,12 +446,12 @@ impl Node {
                 }
             },
             Self::UnaryOp(op, rhs) => {
-                let rhs = rhs.eval(ctx)?;
+                let rhs = rhs.eval_node(ctx)?;
                 op.eval(rhs)
             },
             Self::BinaryOp(op, lhs, rhs) => {
-                let lhs = lhs.eval(ctx)?;
-                let rhs = rhs.eval(ctx)?;
+                let lhs = lhs.eval_node(ctx)?;
+                let rhs = rhs.eval_node(ctx)?;
                 op.eval(lhs, rhs)
             },
             Self::Value(v) => Ok(v.clone()),
@@ -76,22 +460,22 @@ impl Node {
                     "$ctx" => Ok(ctx_to_value(ctx)),
                     x => Err(EvalErrorKind::InvalidSpecialIdent(x.to_owned()).into())
                 }
-            } else if ctx.contains_key(s) {
-                Ok(ctx[s].clone())
+            } else if let Some(v) = ctx.get(s) {
+                Ok(v)
             } else {
                 Err(EvalErrorKind::VariableUnset(s.to_owned()).into())
             },
             Self::List(v) => {
                 let mut items = vec![];
                 for i in v {
-                    items.push(i.eval(ctx)?);
+                    items.push(i.eval_node(ctx)?);
                 }
                 Ok(Value::List(items))
             },
             Self::Block(v) => {
                 let mut last = Value::Void;
                 for i in v {
-                    last = i.eval(ctx)?;
+                    last = i.eval_node(ctx)?;
                 }
                 Ok(last)
             },
@@ -115,16 +499,47 @@ impl Node {
                         }
                     }
                 } 
-                let func = name.eval(ctx)?;
+                let func = name.eval_node(ctx)?;
                 let mut argvals = vec![];
+                let mut named = vec![];
                 for arg in args {
-                    argvals.push(arg.eval(ctx)?);
+                    match arg {
+                        Node::Assign(name, value) => named.push((name.to_owned(), value.eval_node(ctx)?)),
+                        Node::Spread(list) => {
+                            let list = list.eval_node(ctx)?;
+                            argvals.extend(function::list_arg(&list)?.iter().cloned());
+                        },
+                        _ => argvals.push(arg.eval_node(ctx)?)
+                    }
                 }
-                func.eval(argvals)
+                func.eval_named(argvals, named)
             },
-            Self::FunctionCreate(args, inner) => {
-                Ok(Value::Lambda{args: args.to_vec(), func: inner.clone(), ctx: Box::new(ctx.clone())})
-            }
+            Self::FunctionCreate(args, inner, free) => {
+                let ctx = ctx.capture(free.as_deref());
+                Ok(Value::Lambda{args: args.to_vec(), func: inner.clone(), ctx: Box::new(ctx)})
+            },
+            Self::Format(parts) => {
+                let mut res = String::new();
+                for part in parts {
+                    match part {
+                        FormatPart::Literal(s) => res += s,
+                        FormatPart::Expr(node, spec) => res += &spec.format(&node.eval_node(ctx)?)?
+                    }
+                }
+                Ok(Value::Str(res))
+            },
+            Self::Compare(first, rest) => {
+                let mut lhs = first.eval_node(ctx)?;
+                for (op, node) in rest {
+                    let rhs = node.eval_node(ctx)?;
+                    if op.eval(lhs, rhs.clone())? != Value::Bool(true) {
+                        return Ok(Value::Bool(false))
+                    }
+                    lhs = rhs;
+                }
+                Ok(Value::Bool(true))
+            },
+            Self::Spread(_) => Err(EvalErrorKind::Other("'...' can only be used in patterns and function arguments".to_owned()).into())
         }
     }
 }
@@ -132,7 +547,7 @@ impl Node {
 fn include(args: &Vec<Node>, ctx: &mut Context) -> function::Result {
     use {std::io::Read, std::sync::Arc};
     function::bound_args(args.len(), 1, 1)?;
-    let a = args[0].eval(ctx)?;
+    let a = args[0].eval_node(ctx)?;
     if let Value::Str(name) = a {
         let mut buf = String::new();
         let mut f = match std::fs::File::open(name) {
@@ -157,9 +572,9 @@ fn catch(args: &Vec<Node>, ctx: &mut Context) -> function::Result {
     let alt = if args.len() == 1 {
         Value::Void
     } else {
-        args[1].eval(ctx)?
+        args[1].eval_node(ctx)?
     };
-    match args[0].eval(ctx) {
+    match args[0].eval_node(ctx) {
         Ok(x) => Ok(x),
         Err(_) => Ok(alt)
     }
@@ -167,8 +582,8 @@ fn catch(args: &Vec<Node>, ctx: &mut Context) -> function::Result {
 
 fn set(args: &Vec<Node>, ctx: &mut Context) -> function::Result {
     function::bound_args(args.len(), 2, 2)?;
-    let a = args[0].eval(ctx)?;
-    let val = args[1].eval(ctx)?;
+    let a = args[0].eval_node(ctx)?;
+    let val = args[1].eval_node(ctx)?;
     if let Value::Str(name) = a {
         ctx.insert(name, val);
         Ok(Value::Void)
@@ -179,11 +594,9 @@ fn set(args: &Vec<Node>, ctx: &mut Context) -> function::Result {
 
 fn unset(args: &Vec<Node>, ctx: &mut Context) -> function::Result {
     function::bound_args(args.len(), 1, 1)?;
-    let a = args[0].eval(ctx)?;
+    let a = args[0].eval_node(ctx)?;
     if let Value::Str(name) = a {
-        let val = ctx.get(&name).cloned().unwrap_or(Value::Void);
-        ctx.remove(&name);
-        Ok(val)
+        Ok(ctx.remove(&name).unwrap_or(Value::Void))
     } else {
         Err(EvalErrorKind::WrongArgType(a).into())
     }
@@ -191,7 +604,7 @@ fn unset(args: &Vec<Node>, ctx: &mut Context) -> function::Result {
 
 fn is_set(args: &Vec<Node>, ctx: &mut Context) -> function::Result {
     function::bound_args(args.len(), 1, 1)?;
-    let a = args[0].eval(ctx)?;
+    let a = args[0].eval_node(ctx)?;
     if let Value::Str(name) = a {
         Ok(Value::Bool(ctx.contains_key(&name)))
     } else {
@@ -204,7 +617,7 @@ fn get(args: &Vec<Node>, ctx: &mut Context) -> function::Result {
     let a = args[0].eval(ctx)?;
     if let Value::Str(name) = a {
         match ctx.get(&name) {
-            Some(x) => Ok(x.clone()),
+            Some(x) => Ok(x),
             None => Err(EvalErrorKind::VariableUnset(name).into())
         }
     } else {
@@ -238,7 +651,7 @@ pub fn value_to_ctx(value: &Value) -> Option<Context> {
 pub fn ctx_to_value(ctx: &Context) -> Value {
     let mut l = vec![];
     for (k, v) in ctx {
-        l.push(Value::List(vec![Value::Str(k.to_owned()), v.clone()]))
+        l.push(Value::List(vec![Value::Str(k), v]))
     }
     Value::List(l)
 }
@@ -246,7 +659,7 @@ pub fn ctx_to_value(ctx: &Context) -> Value {
 #[derive(Clone, PartialEq)]
 #[allow(dead_code)]
 enum GroupNode {
-    Token(Token), Node(Vec<GroupNode>), List(Vec<GroupNode>)
+    Token(Token), Node(Vec<GroupNode>), List(Vec<GroupNode>), Scope(Vec<GroupNode>)
 }
 
 impl std::fmt::Debug for GroupNode {
@@ -259,6 +672,11 @@ impl std::fmt::Debug for GroupNode {
                     .collect::<Vec<String>>()
                     .join(", "))?,
             Self::List(v) => write!(f, "[{}]", 
+                    v.iter()
+                    .map(|x| format!("{:?}", x))
+                    .collect::<Vec<String>>()
+                    .join(", "))?,
+            Self::Scope(v) => write!(f, "{{{}}}", 
                     v.iter()
                     .map(|x| format!("{:?}", x))
                     .collect::<Vec<String>>()
@@ -268,17 +686,39 @@ impl std::fmt::Debug for GroupNode {
     }
 }
 
+/// Options controlling how expressions are parsed
+#[derive(Clone, Debug, Default)]
+pub struct CompileOptions {
+    /// Treat a call whose only argument is a parenthesized list as a call with the elements of
+    /// the list as arguments, so `foo((1, 2))` is `foo(1, 2)`. This was the behavior of earlier
+    /// versions, kept for old scripts; otherwise use `foo(...xs)` to spread a list.
+    pub unpack_list_args: bool,
+    /// Simplify the tree with [`crate::optimizer::optimize`] after parsing. This assumes that
+    /// the builtin functions and constants are not redefined in the context the expression is
+    /// evaluated in. Leave it off to see the tree as it was written, for example when debugging.
+    pub optimize: bool
+}
+
 pub fn gen_tree(tokens: Vec<Token>) -> Result<Node, TreeError> {
+    gen_tree_with(tokens, &CompileOptions::default())
+}
+
+pub fn gen_tree_with(tokens: Vec<Token>, opts: &CompileOptions) -> Result<Node, TreeError> {
     let parentree = parentree(tokens)?;
-    finish_tree(parentree)
+    let node = finish_tree(parentree, opts)?;
+    if opts.optimize {
+        Ok(crate::optimizer::optimize(node))
+    } else {
+        Ok(node)
+    }
 }
 
-fn finish_tree(root: GroupNode) -> Result<Node, TreeError> {
+fn finish_tree(root: GroupNode, opts: &CompileOptions) -> Result<Node, TreeError> {
     if let GroupNode::Token(a) = root {
         match a {
             Token::BinaryOp(_) | Token::UnaryOp(_) | Token::Assign | Token::AssignOp(_)
-            | Token::Comma | Token::FunctionCall | Token::Colon
-                => Err(TreeError::TokenNoArgs(a)),
+            | Token::Comma | Token::FunctionCall | Token::Colon | Token::Ellipsis | Token::Pipe
+            | Token::Let => Err(TreeError::TokenNoArgs(a)),
             Token::Integer(n) => Ok(Node::Value(Value::Integer(n))),
             Token::Float(n) => Ok(Node::Value(Value::Float(n))),
             Token::Imaginary(n) => Ok(Node::Value(Value::from_complex(0., n))),
@@ -286,46 +726,92 @@ fn finish_tree(root: GroupNode) -> Result<Node, TreeError> {
             Token::False => Ok(Node::Value(Value::Bool(false))),
             Token::Identifier(s) => Ok(Node::Identifier(s)),
             Token::Str(s) => Ok(Node::Value(Value::Str(s))),
+            Token::FStr(parts) => {
+                let mut res = vec![];
+                for part in parts {
+                    res.push(match part {
+                        FStrPart::Literal(s) => FormatPart::Literal(s),
+                        FStrPart::Expr(tokens, spec) => FormatPart::Expr(Box::new(finish_tree(parentree(tokens)?, opts)?), spec)
+                    });
+                }
+                Ok(Node::Format(res))
+            },
             Token::Semicolon => Ok(Node::Value(Value::Void)),
-            Token::LParen | Token::RParen => unreachable!()
+            Token::LParen | Token::RParen | Token::LBrace | Token::RBrace => unreachable!()
+        }
+    } else if let GroupNode::Scope(nodes) = root {
+        if nodes.is_empty() {
+            Ok(Node::Scope(Box::new(Node::Value(Value::Void))))
+        } else {
+            Ok(Node::Scope(Box::new(finish_tree(GroupNode::Node(nodes), opts)?)))
         }
     } else if let GroupNode::Node(nodes) = root {
         if nodes.len() == 0 {
             return Ok(Node::List(vec![]));
         } else if nodes.len() == 1 {
-            return finish_tree(nodes[0].clone());
+            return finish_tree(nodes[0].clone(), opts);
         }
         let (before, pivot, after) = next_split(nodes);
         if let GroupNode::Token(token) = pivot {
             match token {
-                Token::LParen | Token::RParen => unreachable!(),
+                Token::LParen | Token::RParen | Token::LBrace | Token::RBrace => unreachable!(),
                 Token::BinaryOp(op) => if before.len() == 0 || after.len() == 0 {
                     return Err(TreeError::TokenNoArgs(Token::BinaryOp(op)))
+                } else if op.is_comparison() {
+                    // comparisons are the loosest binary operators, so an unparenthesized
+                    // comparison before this one means `before` is the start of a chain
+                    let chained = before.iter().any(|n| matches!(n,
+                        GroupNode::Token(Token::BinaryOp(o)) if o.is_comparison()));
+                    let lhs = finish_tree(GroupNode::Node(before), opts)?;
+                    let rhs = finish_tree(GroupNode::Node(after), opts)?;
+                    return Ok(match lhs {
+                        Node::Compare(first, mut rest) if chained => {
+                            rest.push((op, rhs));
+                            Node::Compare(first, rest)
+                        },
+                        Node::BinaryOp(prev, a, b) if chained && prev.is_comparison()
+                            => Node::Compare(a, vec![(prev, *b), (op, rhs)]),
+                        lhs => Node::BinaryOp(op, Box::new(lhs), Box::new(rhs))
+                    })
                 } else {
                     return Ok(Node::BinaryOp(op, 
-                        Box::new(finish_tree(GroupNode::Node(before))?), 
-                        Box::new(finish_tree(GroupNode::Node(after))?)))
+                        Box::new(finish_tree(GroupNode::Node(before), opts)?), 
+                        Box::new(finish_tree(GroupNode::Node(after), opts)?)))
                 },
                 Token::UnaryOp(op) => if after.len() == 0 {
                     return Err(TreeError::TokenNoArgs(Token::UnaryOp(op)))
                 } else {
                     return Ok(Node::UnaryOp(op,
-                            Box::new(finish_tree(GroupNode::Node(after))?)))
+                            Box::new(finish_tree(GroupNode::Node(after), opts)?)))
                 },
                 Token::Assign => if before.len() == 1 {
                     if let GroupNode::Token(Token::Identifier(name)) = &before[0] {
                         return Ok(Node::Assign(name.to_owned(),
-                            Box::new(finish_tree(GroupNode::Node(after))?)))
+                            Box::new(finish_tree(GroupNode::Node(after), opts)?)))
+                    } else if let GroupNode::Node(_) = &before[0] {
+                        let pattern = Pattern::from_node(&finish_tree(before[0].clone(), opts)?)
+                            .ok_or(TreeError::AssignLeftInvalid)?;
+                        return Ok(Node::AssignPattern(pattern,
+                            Box::new(finish_tree(GroupNode::Node(after), opts)?)))
                     } else {
                         return Err(TreeError::AssignLeftInvalid)
                     }
                 } else {
                     return Err(TreeError::AssignLeftInvalid)
                 },
+                Token::Let => if !before.is_empty() || after.is_empty() {
+                    return Err(TreeError::TokenNoArgs(Token::Let))
+                } else {
+                    return match finish_tree(GroupNode::Node(after), opts)? {
+                        Node::Assign(name, value) => Ok(Node::Let(Pattern::Ident(name), value)),
+                        Node::AssignPattern(pattern, value) => Ok(Node::Let(pattern, value)),
+                        _ => Err(TreeError::LetNotAssign)
+                    }
+                },
                 Token::AssignOp(op) => if before.len() == 1 {
                     if let GroupNode::Token(Token::Identifier(name)) = &before[0] {
                         return Ok(Node::AssignOp(op, name.to_owned(),
-                            Box::new(finish_tree(GroupNode::Node(after))?)))
+                            Box::new(finish_tree(GroupNode::Node(after), opts)?)))
                     } else {
                         return Err(TreeError::AssignLeftInvalid)
                     }
@@ -334,23 +820,49 @@ fn finish_tree(root: GroupNode) -> Result<Node, TreeError> {
                 },
                 Token::FunctionCall => {
                     let name = if before.len() == 1 {
-                        finish_tree(before[0].clone())?
+                        finish_tree(before[0].clone(), opts)?
                     } else {
-                        finish_tree(GroupNode::Node(before))?
+                        finish_tree(GroupNode::Node(before), opts)?
+                    };
+                    // without `unpack_list_args`, `foo((1, 2))` has a single argument, which
+                    // is distinguished from `foo(1, 2)` by the extra group
+                    let single = match &after[..] {
+                        [GroupNode::Node(inner)] => matches!(&inner[..], [GroupNode::Node(_)]),
+                        _ => false
                     };
-                    let args = finish_tree(GroupNode::Node(after))?;
-                    if let Node::List(args) = args {
+                    let args = finish_tree(GroupNode::Node(after), opts)?;
+                    if single && !opts.unpack_list_args {
+                        return Ok(Node::FunctionCall(Box::new(name), vec![args]))
+                    } else if let Node::List(args) = args {
                         return Ok(Node::FunctionCall(Box::new(name), args))
                     } else {
                         return Ok(Node::FunctionCall(Box::new(name), vec![args]))
                     }
                 },
+                Token::Pipe => if before.is_empty() || after.is_empty() {
+                    return Err(TreeError::TokenNoArgs(Token::Pipe))
+                } else {
+                    // `x |> f(a)` is `f(x, a)`, and `x |> f` is `f(x)`
+                    let arg = finish_tree(GroupNode::Node(before), opts)?;
+                    return match finish_tree(GroupNode::Node(after), opts)? {
+                        Node::FunctionCall(func, mut args) => {
+                            args.insert(0, arg);
+                            Ok(Node::FunctionCall(func, args))
+                        },
+                        func => Ok(Node::FunctionCall(Box::new(func), vec![arg]))
+                    }
+                },
+                Token::Ellipsis => if !before.is_empty() || after.is_empty() {
+                    return Err(TreeError::TokenNoArgs(Token::Ellipsis))
+                } else {
+                    return Ok(Node::Spread(Box::new(finish_tree(GroupNode::Node(after), opts)?)))
+                },
                 Token::Comma => {
                     let mut items = vec![];
                     if before.len() == 0 {
                         return Err(TreeError::TokenNoArgs(Token::Comma))
                     }
-                    items.push(finish_tree(GroupNode::Node(before))?);
+                    items.push(finish_tree(GroupNode::Node(before), opts)?);
                     if after.len() > 0 {
                         let mut cdr = after;
                         loop {
@@ -358,14 +870,14 @@ fn finish_tree(root: GroupNode) -> Result<Node, TreeError> {
                             let (car, pivot) = (split.0, split.1);
                             let next_cdr = split.2;
                             if pivot == GroupNode::Token(Token::Comma) {
-                                items.push(finish_tree(GroupNode::Node(car))?);
+                                items.push(finish_tree(GroupNode::Node(car), opts)?);
                                 if next_cdr.len() == 0 {
                                     break
                                 } else {
                                     cdr = next_cdr;
                                 }
                             } else {
-                                items.push(finish_tree(GroupNode::Node(cdr))?);
+                                items.push(finish_tree(GroupNode::Node(cdr), opts)?);
                                 break
                             }
                         }
@@ -374,15 +886,15 @@ fn finish_tree(root: GroupNode) -> Result<Node, TreeError> {
                 },
                 Token::Semicolon => {
                     if before.len() == 0 {
-                        return finish_tree(GroupNode::Node(after))
+                        return finish_tree(GroupNode::Node(after), opts)
                     } else if after.len() == 0 {
                         return Ok(Node::Block(vec![
-                            finish_tree(GroupNode::Node(before))?,
+                            finish_tree(GroupNode::Node(before), opts)?,
                             Node::Value(Value::Void)
                         ]))
                     }
-                    let before = finish_tree(GroupNode::Node(before))?;
-                    let after = finish_tree(GroupNode::Node(after))?;
+                    let before = finish_tree(GroupNode::Node(before), opts)?;
+                    let after = finish_tree(GroupNode::Node(after), opts)?;
                     if let Node::Block(mut v) = after {
                         v.insert(0, before);
                         return Ok(Node::Block(v));
@@ -391,24 +903,17 @@ fn finish_tree(root: GroupNode) -> Result<Node, TreeError> {
                     }
                 },
                 Token::Colon => {
-                    let before = finish_tree(GroupNode::Node(before))?;
-                    let after = finish_tree(GroupNode::Node(after))?;
-                    let params = match before {
-                        Node::Identifier(s) => vec![s],
-                        Node::List(l) => {
-                            let mut r = vec![];
-                            for i in l {
-                                if let Node::Identifier(s) = i {
-                                    r.push(s);
-                                } else {
-                                    return Err(TreeError::ColonLeftNotIdentifier(i))
-                                }
-                            }
-                            r
-                        },
-                        _ => return Err(TreeError::ColonLeftNotIdentifier(before))
+                    let before = finish_tree(GroupNode::Node(before), opts)?;
+                    let after = finish_tree(GroupNode::Node(after), opts)?;
+                    let params = match &before {
+                        Node::Identifier(s) => Some(vec![Pattern::Ident(s.to_owned())]),
+                        Node::List(l) => Pattern::from_nodes(l),
+                        Node::Spread(_) | Node::Assign(..) => Pattern::from_nodes(std::slice::from_ref(&before)),
+                        _ => None
                     };
-                    return Ok(Node::FunctionCreate(params, Box::new(after)))
+                    let params = params.ok_or(TreeError::ColonLeftNotIdentifier(before))?;
+                    let free = free_vars(&params, &after);
+                    return Ok(Node::FunctionCreate(params, Box::new(after), free))
 
                 },
                 x => {
@@ -416,7 +921,8 @@ fn finish_tree(root: GroupNode) -> Result<Node, TreeError> {
                 }
             }
         } else {
-            unreachable!()
+            // two groups next to each other, such as `{a} {b}`
+            Err(TreeError::MissingOperator)
         }
     } else {
         unreachable!()
@@ -443,31 +949,40 @@ fn next_split(nodes: Vec<GroupNode>) -> (Vec<GroupNode>, GroupNode, Vec<GroupNod
 }
 
 fn parentree(tokens: Vec<Token>) -> Result<GroupNode, TreeError> {
-    let mut depth = 0;
+    // the opening parentheses and braces that have not been closed yet
+    let mut open = vec![];
     let mut subtree = vec![];
     let mut res = vec![];
     let mut last_token_op = true;
     for token in tokens {
-        if token == Token::LParen {
-            if depth > 0 {
-                subtree.push(token);
+        if token == Token::LParen || token == Token::LBrace {
+            if !open.is_empty() {
+                subtree.push(token.clone());
+            }
+            open.push(token);
+        } else if token == Token::RParen || token == Token::RBrace {
+            let opener = open.pop();
+            match (&opener, &token) {
+                (Some(Token::LParen), Token::RParen) | (Some(Token::LBrace), Token::RBrace) => (),
+                (_, Token::RParen) => return Err(TreeError::NoLParen),
+                _ => return Err(TreeError::NoLBrace)
             }
-            depth += 1;
-        } else if token == Token::RParen {
-            depth -= 1;
-            if depth < 0 {
-                return Err(TreeError::NoLParen);
-            } else if depth == 0 {
+            if !open.is_empty() {
+                subtree.push(token);
+            } else if token == Token::RParen {
                 if !last_token_op {
                     res.push(GroupNode::Token(Token::FunctionCall));
                 }
-                res.push(parentree(subtree)?);
-                subtree = vec![];
+                res.push(parentree(std::mem::take(&mut subtree))?);
                 last_token_op = false;
             } else {
-                subtree.push(token);
+                match parentree(std::mem::take(&mut subtree))? {
+                    GroupNode::Node(nodes) => res.push(GroupNode::Scope(nodes)),
+                    _ => unreachable!()
+                }
+                last_token_op = false;
             }
-        } else if depth > 0 {
+        } else if !open.is_empty() {
             subtree.push(token);
         } else {
             let is_op = token.is_op();
@@ -483,8 +998,9 @@ fn parentree(tokens: Vec<Token>) -> Result<GroupNode, TreeError> {
             last_token_op = is_op;
         }
     }
-    if depth > 0 {
-        return Err(TreeError::NoRParen);
+    match open.last() {
+        Some(Token::LParen) => Err(TreeError::NoRParen),
+        Some(_) => Err(TreeError::NoRBrace),
+        None => Ok(GroupNode::Node(res))
     }
-    Ok(GroupNode::Node(res))
 }
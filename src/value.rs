@@ -1,6 +1,6 @@
 use num_rational;
 use num_complex;
-use std::ops::{Add, Sub, Mul, Div, Rem, Neg};
+use std::ops::{Add, Sub, Mul, Div, Rem, Neg, BitAnd, BitOr, BitXor, Shl, Shr, Not};
 use std::cmp::Ordering;
 use crate::function::{self, Function, EvalError, EvalErrorKind};
 pub type Complex = num_complex::Complex<f64>;
@@ -17,8 +17,11 @@ pub enum Value {
     List(List),
     Str(String),
     Function(Function),
-    Lambda{args: Vec<String>, func: Box<crate::tree::Node>, ctx: Box<crate::Context>},
+    Lambda{args: Vec<crate::tree::Pattern>, func: Box<crate::tree::Node>, ctx: Box<crate::context::Captured>},
     Builtin(std::sync::Arc<dyn ValueBuiltin + Send + Sync>),
+    Iter(std::sync::Arc<crate::lazy::Lazy>),
+    /// A mutable cell created with `ref(v)`. Copies of a reference share the same cell.
+    Ref(std::sync::Arc<std::sync::Mutex<Value>>),
     Void
 }
 
@@ -79,19 +82,46 @@ impl Value {
     pub fn eval(&self, args: Vec<Self>) -> Result<Self, EvalError> {
         match self {
             Self::Function(f) => f.0(args),
-            Self::Lambda{args: argnames, func, ctx} => {
-                function::bound_args(argnames.len(), args.len(), args.len())?;
-                let mut ctx = ctx.clone();
-                for i in 0..args.len() {
-                    ctx.insert(argnames[i].to_string(), args[i].clone());
-                }
-                func.eval(&mut ctx)
-            }
+            Self::Lambda{..} => self.eval_named(args, vec![]),
             Self::Bool(true) => function::func_true(args),
             Self::Bool(false) => function::func_false(args),
             _ => Err(EvalErrorKind::WrongFunc(self.clone()).into())
         }
     }
+    /// Calls a function with positional and named arguments. Only lambdas accept named
+    /// arguments.
+    pub fn eval_named(&self, args: Vec<Self>, named: Vec<(String, Self)>) -> Result<Self, EvalError> {
+        match self {
+            Self::Lambda{args: params, func, ctx} => {
+                let _depth = crate::limits::enter()?;
+                let mut ctx = ctx.context();
+                ctx.push_call_frame();
+                crate::tree::Pattern::bind_args(params, args, named, &mut ctx)?;
+                func.eval(&mut ctx)
+            },
+            _ if named.is_empty() => self.eval(args),
+            _ => Err(EvalErrorKind::NamedArg(named[0].0.clone()).into())
+        }
+    }
+    /// Creates a [`Value::Ref`] holding `val`
+    pub fn new_ref(val: Value) -> Self {
+        Self::Ref(std::sync::Arc::new(std::sync::Mutex::new(val)))
+    }
+    /// Returns the value held by a [`Value::Ref`], or `None` for other values
+    pub fn get_ref(&self) -> Option<Value> {
+        match self {
+            Self::Ref(cell) => Some(cell.lock().unwrap().clone()),
+            _ => None
+        }
+    }
+    /// Replaces the value held by a [`Value::Ref`], returning the previous value. Fails if
+    /// `self` is not a reference.
+    pub fn set_ref(&self, val: Value) -> Result<Value, EvalError> {
+        match self {
+            Self::Ref(cell) => Ok(std::mem::replace(&mut *cell.lock().unwrap(), val)),
+            _ => Err(EvalErrorKind::WrongOpArgTypes(self.clone(), val).into())
+        }
+    }
     pub fn is_nan(&self) -> bool {
         match self {
             Value::Float(f) => f.is_nan(),
@@ -106,6 +136,105 @@ impl Value {
             _ => false
         }
     }
+    /// A total order over all values, used for sorting. Values of different kinds are ordered
+    /// void < booleans < numbers < strings < lists < builtin functions < lambdas < builtin
+    /// values < sequences < references. Numbers of all types are compared by real part, then by
+    /// imaginary part (zero for real numbers), with NaN greater than every other number and
+    /// equal to itself. Integers, ratios and floats are compared exactly, without converting
+    /// them to floats first, so large integers that `==` considers equal to a float may not be
+    /// equal in this order. Strings are compared by Unicode code point and lists
+    /// lexicographically. Functions, builtin values, sequences and references are all equal to
+    /// other values of the same kind.
+    pub fn cmp_total(&self, other: &Self) -> Ordering {
+        fn rank(v: &Value) -> u8 {
+            match v {
+                Void => 0,
+                Bool(_) => 1,
+                Integer(_) | Ratio(_) | Float(_) | Complex(_) => 2,
+                Str(_) => 3,
+                List(_) => 4,
+                Function(_) => 5,
+                Lambda{..} => 6,
+                Builtin(_) => 7,
+                Iter(_) => 8,
+                Ref(_) => 9
+            }
+        }
+        /// A real part, either a fraction with a positive denominator or a float
+        enum Real {
+            Exact(i128, i128),
+            Float(f64)
+        }
+        fn parts(v: &Value) -> (Real, f64) {
+            match v {
+                Integer(n) => (Real::Exact(*n as i128, 1), 0.0),
+                Ratio(n) => (Real::Exact(*n.numer() as i128, *n.denom() as i128), 0.0),
+                Float(n) => (Real::Float(*n), 0.0),
+                Complex(n) => (Real::Float(n.re), n.im),
+                _ => unreachable!()
+            }
+        }
+        fn cmp_f64(a: f64, b: f64) -> Ordering {
+            a.partial_cmp(&b).unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+        }
+        /// Compares the fraction `n/d` (with `d` positive and the fraction in the range of `i64`)
+        /// to a float exactly, by writing the float as an integer times a power of two
+        fn cmp_exact_f64(n: i128, d: i128, f: f64) -> Ordering {
+            const MAX: f64 = 9223372036854775808.0; // 2^63
+            if f.is_nan() || f >= MAX {
+                return Ordering::Less
+            } else if f < -MAX {
+                return Ordering::Greater
+            }
+            let bits = f.to_bits();
+            let exp = ((bits >> 52) & 0x7ff) as i32;
+            let mantissa = (bits & ((1 << 52) - 1)) as i128;
+            let (mut m, e) = match exp {
+                0 => (mantissa, -1074),
+                _ => (mantissa | 1 << 52, exp - 1075)
+            };
+            if bits >> 63 == 1 {
+                m = -m;
+            }
+            // f = m * 2^e, and |f| < 2^63 so an integer f fits in an i128 times d
+            if e >= 0 {
+                return n.cmp(&((m << e) * d))
+            }
+            // compare n with md / 2^k, rounding the division down; shifts past the 116 bits of
+            // md round down the same way
+            let md = m * d;
+            let k = (-e).min(127);
+            let q = md >> k;
+            n.cmp(&q).then(if md != q << k { Ordering::Less } else { Ordering::Equal })
+        }
+        fn cmp_real(a: Real, b: Real) -> Ordering {
+            match (a, b) {
+                (Real::Exact(n1, d1), Real::Exact(n2, d2)) => (n1 * d2).cmp(&(n2 * d1)),
+                (Real::Exact(n, d), Real::Float(f)) => cmp_exact_f64(n, d, f),
+                (Real::Float(f), Real::Exact(n, d)) => cmp_exact_f64(n, d, f).reverse(),
+                (Real::Float(a), Real::Float(b)) => cmp_f64(a, b)
+            }
+        }
+        use Value::*;
+        match (self, other) {
+            (Bool(a), Bool(b)) => a.cmp(b),
+            (Str(a), Str(b)) => a.cmp(b),
+            (List(a), List(b)) => {
+                for (x, y) in a.iter().zip(b) {
+                    let ord = x.cmp_total(y);
+                    if ord != Ordering::Equal {
+                        return ord
+                    }
+                }
+                a.len().cmp(&b.len())
+            },
+            _ if rank(self) == 2 && rank(other) == 2 => {
+                let (a, b) = (parts(self), parts(other));
+                cmp_real(a.0, b.0).then(cmp_f64(a.1, b.1))
+            },
+            _ => rank(self).cmp(&rank(other))
+        }
+    }
 }
 
 impl PartialOrd<Value> for Value {
@@ -134,6 +263,12 @@ impl PartialEq<Value> for Value {
     fn eq(&self, other: &Self) -> bool {
         use Value::*;
         use num_traits::Zero;
+        // a sequence is equal to a list with the same elements, and the sequence is only
+        // evaluated as far as the length of the list
+        if let (Iter(seq), List(l)) | (List(l), Iter(seq)) = (self, other) {
+            let mut items = seq.iter();
+            return l.iter().all(|x| matches!(items.next(), Some(Ok(y)) if y == *x)) && items.next().is_none()
+        }
         let sorted = sort(self, other);
         match (sorted.0, sorted.1) {
             (Integer(a), Integer(b)) => a == b,
@@ -149,16 +284,77 @@ impl PartialEq<Value> for Value {
             (List(a), List(b)) => a == b,
             (Bool(a), Bool(b)) => a == b,
             (Str(a), Str(b)) => a == b,
+            (Ref(a), Ref(b)) => std::sync::Arc::ptr_eq(a, b),
             (Void, Void) => true,
             (_,_) => false,
         }
     }
 }
 
+/// Hashes values consistently with `==`: numbers that are equal have the same hash whatever
+/// their types, since they are hashed by their value as floats. Functions and builtin values
+/// are never equal to anything, and only hash their kind; references hash by identity.
+/// Sequences only hash their kind, although they can be equal to lists, so they are not
+/// [`Value::is_hashable`].
+impl std::hash::Hash for Value {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        use Value::*;
+        fn float_bits(x: f64) -> u64 {
+            // 0.0 == -0.0
+            if x == 0.0 { 0 } else { x.to_bits() }
+        }
+        match self {
+            Integer(_) | Float(_) | Complex(_) | Ratio(_) => {
+                let (re, im) = match self {
+                    Integer(n) => (*n as f64, 0.0),
+                    Float(n) => (*n, 0.0),
+                    Ratio(n) => (r2f64(n), 0.0),
+                    Complex(n) => (n.re, n.im),
+                    _ => unreachable!()
+                };
+                2u8.hash(state);
+                float_bits(re).hash(state);
+                float_bits(im).hash(state);
+            },
+            Void => 0u8.hash(state),
+            Bool(b) => { 1u8.hash(state); b.hash(state) },
+            Str(s) => { 3u8.hash(state); s.hash(state) },
+            List(l) => { 4u8.hash(state); l.hash(state) },
+            Function(_) => 5u8.hash(state),
+            Lambda{..} => 6u8.hash(state),
+            Builtin(_) => 7u8.hash(state),
+            Iter(_) => 8u8.hash(state),
+            Ref(cell) => { 9u8.hash(state); std::sync::Arc::as_ptr(cell).hash(state) }
+        }
+    }
+}
+
+impl Value {
+    /// Whether the value is equal to itself and to any value with the same contents, so it can
+    /// be used to look up a cached result. False for NaN, functions, sequences, builtin values
+    /// and references, as well as lists containing them.
+    pub fn is_hashable(&self) -> bool {
+        match self {
+            Self::Integer(_) | Self::Ratio(_) | Self::Bool(_) | Self::Str(_) | Self::Void => true,
+            Self::Float(_) | Self::Complex(_) => !self.is_nan(),
+            Self::List(l) => l.iter().all(|v| v.is_hashable()),
+            _ => false
+        }
+    }
+}
+
 impl Add<Value> for Value {
     type Output = Result<Value, EvalError>;
     fn add(self, rhs: Value) -> Self::Output {
         use Value::*;
+        // a sequence added to a list is collected first
+        if let (Iter(seq), List(_)) = (&self, &rhs) {
+            return List(seq.collect()?) + rhs
+        }
+        if let (List(_), Iter(seq)) = (&self, &rhs) {
+            let rhs = List(seq.collect()?);
+            return self + rhs
+        }
         // Integer, Float, Complex, Ratio, Bool, List, Void
         match sort(&self, &rhs) {
             (Integer(a), Integer(b),_) => Ok(Integer(a+b)),
@@ -172,7 +368,10 @@ impl Add<Value> for Value {
             (Complex(a), Ratio(b),_) => Ok(Complex(a + r2f64(b))),
             (Ratio(a), Ratio(b),_) => Ok(Ratio(a + b)),
             (Bool(a), Bool(b),_) => Ok(Bool(a | b)),
-            (List(a), List(b),_) => Ok(List(a.iter().chain(b.iter()).cloned().collect())),
+            (List(a), List(b),_) => {
+                crate::limits::check_len(a.len() + b.len())?;
+                Ok(List(a.iter().chain(b.iter()).cloned().collect()))
+            },
             (Str(a), Str(b), false) => Ok(Str(a.to_owned() + b)),
             (Str(a), Str(b), true) => Ok(Str(b.to_owned() + a)),
             (_,_,_) => Err(EvalErrorKind::WrongOpArgTypes(self, rhs).into())
@@ -323,7 +522,135 @@ impl Neg for Value {
     }
 }
 
+impl BitAnd<Value> for Value {
+    type Output = Result<Value, EvalError>;
+    fn bitand(self, rhs: Value) -> Self::Output {
+        use Value::*;
+        match (self, rhs) {
+            (Integer(a), Integer(b)) => Ok(Integer(a & b)),
+            (Bool(a), Bool(b)) => Ok(Bool(a & b)),
+            (a,b) => Err(EvalErrorKind::WrongOpArgTypes(a, b).into())
+        }
+    }
+}
+
+impl BitOr<Value> for Value {
+    type Output = Result<Value, EvalError>;
+    fn bitor(self, rhs: Value) -> Self::Output {
+        use Value::*;
+        match (self, rhs) {
+            (Integer(a), Integer(b)) => Ok(Integer(a | b)),
+            (Bool(a), Bool(b)) => Ok(Bool(a | b)),
+            (a,b) => Err(EvalErrorKind::WrongOpArgTypes(a, b).into())
+        }
+    }
+}
+
+impl BitXor<Value> for Value {
+    type Output = Result<Value, EvalError>;
+    fn bitxor(self, rhs: Value) -> Self::Output {
+        use Value::*;
+        match (self, rhs) {
+            (Integer(a), Integer(b)) => Ok(Integer(a ^ b)),
+            (Bool(a), Bool(b)) => Ok(Bool(a ^ b)),
+            (a,b) => Err(EvalErrorKind::WrongOpArgTypes(a, b).into())
+        }
+    }
+}
+
+/// Shift amounts must be between 0 and 63
+fn shift_amount(n: i64) -> Result<u32, EvalError> {
+    if (0..64).contains(&n) {
+        Ok(n as u32)
+    } else {
+        Err(EvalErrorKind::WrongArgValue(Value::Integer(n)).into())
+    }
+}
+
+impl Shl<Value> for Value {
+    type Output = Result<Value, EvalError>;
+    fn shl(self, rhs: Value) -> Self::Output {
+        use Value::*;
+        match (self, rhs) {
+            (Integer(a), Integer(b)) => Ok(Integer(a << shift_amount(b)?)),
+            (a,b) => Err(EvalErrorKind::WrongOpArgTypes(a, b).into())
+        }
+    }
+}
+
+impl Shr<Value> for Value {
+    type Output = Result<Value, EvalError>;
+    fn shr(self, rhs: Value) -> Self::Output {
+        use Value::*;
+        match (self, rhs) {
+            (Integer(a), Integer(b)) => Ok(Integer(a >> shift_amount(b)?)),
+            (a,b) => Err(EvalErrorKind::WrongOpArgTypes(a, b).into())
+        }
+    }
+}
+
+impl Not for Value {
+    type Output = Result<Value, EvalError>;
+    fn not(self) -> Self::Output {
+        use Value::*;
+        match self {
+            Integer(a) => Ok(Integer(!a)),
+            Bool(a) => Ok(Bool(!a)),
+            a => Err(EvalErrorKind::WrongArgType(a).into())
+        }
+    }
+}
+
 impl Value {
+    /// Compares two values for the ordering operators. Numbers, booleans, strings, void and
+    /// lists of comparable values can be ordered; the result is `None` if a NaN is involved.
+    /// Fails for values that cannot be ordered, such as a string and a number, or complex
+    /// numbers.
+    pub fn try_cmp(&self, other: &Self) -> Result<Option<Ordering>, EvalError> {
+        use Value::*;
+        match (self, other) {
+            (List(a), List(b)) => {
+                for (x, y) in a.iter().zip(b) {
+                    match x.try_cmp(y)? {
+                        Some(Ordering::Equal) => (),
+                        res => return Ok(res)
+                    }
+                }
+                Ok(Some(a.len().cmp(&b.len())))
+            },
+            (Integer(_) | Float(_) | Ratio(_), Integer(_) | Float(_) | Ratio(_)) => Ok(self.partial_cmp(other)),
+            _ => match self.partial_cmp(other) {
+                Some(res) => Ok(Some(res)),
+                None => Err(EvalErrorKind::WrongOpArgTypes(self.clone(), other.clone()).into())
+            }
+        }
+    }
+    /// Division rounded towards negative infinity. Integers and ratios give an integer, floats
+    /// give a float with an integer value.
+    pub fn div_floor(self, rhs: Value) -> Result<Value, EvalError> {
+        use Value::*;
+        use num_traits::Zero;
+        match (self, rhs) {
+            (Integer(a), Integer(b)) => if b.is_zero() {
+                Err(EvalErrorKind::WrongArgValue(Integer(b)).into())
+            } else {
+                match a.checked_div(b) {
+                    Some(q) if a % b != 0 && (a < 0) != (b < 0) => Ok(Integer(q - 1)),
+                    Some(q) => Ok(Integer(q)),
+                    None => Err(EvalErrorKind::WrongArgValue(Integer(b)).into())
+                }
+            },
+            (a @ (Integer(_) | Ratio(_)), b @ (Integer(_) | Ratio(_))) => match (a / b)? {
+                Ratio(r) => Ok(Integer(r.floor().to_integer())),
+                x => Ok(x)
+            },
+            (a @ (Integer(_) | Float(_) | Ratio(_)), b @ (Integer(_) | Float(_) | Ratio(_))) => match (a / b)? {
+                Float(f) => Ok(Float(f.floor())),
+                x => Ok(x)
+            },
+            (a,b) => Err(EvalErrorKind::WrongOpArgTypes(a, b).into())
+        }
+    }
     pub fn pow(self, rhs: Value) -> Result<Value, EvalError> {
         use Value::*;
         use std::convert::TryInto;
@@ -394,6 +721,8 @@ impl Value {
             Self::Function(_) => "builtin function",
             Self::Lambda{..} => "lambda function",
             Self::Builtin(_) => "raw value",
+            Self::Iter(_) => "iterator",
+            Self::Ref(_) => "ref",
             Self::Void => "void"
         }.to_owned()
     }
@@ -449,6 +778,9 @@ impl Value {
     pub fn as_str(&self) -> Option<String> {
         match self { Self::Str(s) => Some(s.to_owned()), _ => None }
     }
+    pub fn is_ref(&self) -> bool {
+        match self { Self::Ref(_) => true, _ => false }
+    }
     pub fn is_callable(&self) -> bool {
         match self { 
             Self::Lambda{..} => true, 
@@ -457,6 +789,10 @@ impl Value {
             _ => false
         }
     }
+    /// Whether the value is a builtin function or a lambda: callable, but not a boolean
+    pub fn is_function(&self) -> bool {
+        self.is_callable() && !self.is_bool()
+    }
 }
 
 
@@ -501,8 +837,8 @@ impl std::fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s = match self {
             Self::Integer(n) => n.to_string(),
-            Self::Float(n) => n.to_string(),
-            Self::Complex(n) => format!("{}+{}i", n.re, n.im),
+            Self::Float(n) => crate::format::display_float(*n, false),
+            Self::Complex(n) => crate::format::display_complex(n.re, n.im, false),
             Self::Ratio(n) => format!("{}//{}", n.numer(), n.denom()),
             Self::Bool(n) => n.to_string(),
             Self::List(n) => format!("({})", n.iter()
@@ -513,6 +849,12 @@ impl std::fmt::Display for Value {
             Self::Function(_) => "<builtin function>".to_owned(),
             Self::Lambda{args,..} => format!("<lambda function of {} args>", args.len()),
             Self::Builtin(v) => {use std::fmt::Debug; v.fmt(f)?; return Ok(())},
+            Self::Iter(_) => "<iterator>".to_owned(),
+            // a reference that is already locked is being printed from inside itself
+            Self::Ref(cell) => match cell.try_lock() {
+                Ok(v) => format!("ref({})", v),
+                Err(_) => "ref(...)".to_owned()
+            },
             Self::Void => "".to_owned()
         };
         write!(f, "{}", s)
@@ -523,8 +865,8 @@ impl std::fmt::Debug for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s = match self {
             Self::Integer(n) => format!("{:?}", n),
-            Self::Float(n) => format!("{:?}", n),
-            Self::Complex(n) => format!("{:?}+{:?}i", n.re, n.im),
+            Self::Float(n) => crate::format::display_float(*n, true),
+            Self::Complex(n) => crate::format::display_complex(n.re, n.im, true),
             Self::Ratio(n) => format!("{:?}//{:?}", n.numer(), n.denom()),
             Self::Bool(n) => n.to_string(),
             Self::List(n) => 
@@ -536,6 +878,11 @@ impl std::fmt::Debug for Value {
             Self::Function(f) => format!("{:?}", f),
             Self::Lambda{args,..} => format!("<function of {} args>", args.len()),
             Self::Builtin(v) => {v.fmt(f)?; return Ok(())},
+            Self::Iter(_) => "<iterator>".to_owned(),
+            Self::Ref(cell) => match cell.try_lock() {
+                Ok(v) => format!("ref({:?})", v),
+                Err(_) => "ref(...)".to_owned()
+            },
             Self::Void => "<void>".to_owned()
         };
         write!(f, "{}", s)
@@ -0,0 +1,428 @@
+//! A bytecode compiler and stack machine for expressions that are evaluated many times.
+//!
+//! [`Program::compile`] turns a [`Node`] into a list of instructions in which every variable
+//! is resolved to a numbered slot, so evaluating it does not look up names in the [`Context`].
+//! A [`Vm`] keeps the slots between runs, and [`Vm::set`] changes a variable without going
+//! through the context, which makes evaluating the same expression with different inputs much
+//! faster than [`Node::eval`]. [`Program::bind`] declares the variables that are inputs, and
+//! returns a [`Bound`] program that is called with their values like a function.
+//!
+//! Programs have the same semantics as [`Node::eval`]. Assignments are written through to the
+//! context, and the slots are reloaded whenever the context changes, for example when a lambda
+//! called by the program assigns to a variable. Lambdas, scopes, `let`, patterns, named and
+//! spread arguments and special functions such as `$set` are not compiled to instructions; they
+//! are evaluated by walking the tree, using the same context.
+
+use std::sync::Arc;
+use crate::{Value, Context, Node};
+use crate::ops::{BinaryOp, UnaryOp};
+use crate::format::FormatSpec;
+use crate::function::{EvalError, EvalErrorKind, bound_args};
+use crate::tree::FormatPart;
+
+#[derive(Clone, Debug)]
+enum Op {
+    /// Push a constant
+    Const(usize),
+    /// Push the value of a variable
+    Load(usize),
+    /// Pop a value and assign it to a variable
+    Store(usize),
+    Pop,
+    Binary(BinaryOp),
+    Unary(UnaryOp),
+    /// Pop this many values and push them as a list
+    List(usize),
+    /// Pop this many arguments and then a function, and push the result of calling it
+    Call(usize),
+    /// Pop a value and push it formatted as a string
+    Format(usize),
+    /// Pop this many strings and push them joined together
+    Concat(usize),
+    /// Pop two values and compare them. If the comparison holds, push the right one for the
+    /// next comparison in a chain, otherwise push false and jump to the end of the chain
+    CompareOrJump(BinaryOp, usize),
+    /// Pop two values and push the result of comparing them as a boolean
+    Compare(BinaryOp),
+    /// Evaluate a node by walking the tree and push the result
+    Tree(usize)
+}
+
+#[derive(Debug)]
+struct ProgramData {
+    code: Vec<Op>,
+    consts: Vec<Value>,
+    specs: Vec<FormatSpec>,
+    nodes: Vec<Node>,
+    slots: Vec<String>
+}
+
+/// An expression compiled to bytecode. Cloning a program is cheap, since clones share the
+/// instructions.
+#[derive(Clone, Debug)]
+pub struct Program(Arc<ProgramData>);
+
+impl Program {
+    /// Compile a node. This always succeeds: the parts of the node that have no instructions
+    /// of their own are evaluated with [`Node::eval`] when the program runs.
+    pub fn compile(node: &Node) -> Self {
+        let mut data = ProgramData {
+            code: vec![], consts: vec![], specs: vec![], nodes: vec![], slots: vec![]
+        };
+        data.emit(node);
+        Self(Arc::new(data))
+    }
+
+    /// The slot of a variable used by the program, to use with [`Vm::set`] and [`Vm::get`].
+    /// Variables that are only used inside lambdas or other parts evaluated as a tree do not
+    /// have slots.
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.0.slots.iter().position(|s| s == name)
+    }
+
+    /// The names of the variables with slots, in slot order.
+    pub fn slots(&self) -> &[String] {
+        &self.0.slots
+    }
+
+    /// Evaluate the program once with the given context, like [`Node::eval`].
+    pub fn eval(&self, ctx: &mut Context) -> Result<Value, EvalError> {
+        Vm::new(self, ctx).run()
+    }
+
+    /// Create a [`Vm`] to run the program repeatedly, sharing the variables of `ctx`.
+    pub fn vm(&self, ctx: &Context) -> Vm {
+        Vm::new(self, ctx)
+    }
+
+    /// Declare the parameters of the program, to call it with their values like a function.
+    /// The parameters are local to the program: they shadow variables of `ctx` with the same
+    /// names without changing them. Other variables are shared with `ctx`, as with [`Vm::new`].
+    pub fn bind(&self, ctx: &Context, params: &[&str]) -> Bound {
+        let mut ctx = ctx.share();
+        ctx.push_frame();
+        for p in params {
+            ctx.declare((*p).to_owned(), Value::Void);
+        }
+        Bound {
+            params: params.iter().map(|p| match self.slot(p) {
+                Some(slot) => Param::Slot(slot),
+                None => Param::Name((*p).to_owned())
+            }).collect(),
+            vm: Vm::new(self, &ctx),
+            ctx
+        }
+    }
+}
+
+impl ProgramData {
+    fn slot(&mut self, name: &str) -> usize {
+        match self.slots.iter().position(|s| s == name) {
+            Some(i) => i,
+            None => {
+                self.slots.push(name.to_owned());
+                self.slots.len() - 1
+            }
+        }
+    }
+
+    fn constant(&mut self, val: Value) {
+        self.consts.push(val);
+        self.code.push(Op::Const(self.consts.len() - 1));
+    }
+
+    fn emit(&mut self, node: &Node) {
+        match node {
+            Node::Value(v) => self.constant(v.clone()),
+            Node::Identifier(s) if !s.starts_with('$') => {
+                let slot = self.slot(s);
+                self.code.push(Op::Load(slot));
+            },
+            Node::Assign(name, value) if crate::tree::check_reserved(name).is_ok() => {
+                self.emit(value);
+                let slot = self.slot(name);
+                self.code.push(Op::Store(slot));
+                self.constant(Value::Void);
+            },
+            Node::AssignOp(op, name, value) if crate::tree::check_reserved(name).is_ok() => {
+                let slot = self.slot(name);
+                self.code.push(Op::Load(slot));
+                self.emit(value);
+                self.code.push(Op::Binary(*op));
+                self.code.push(Op::Store(slot));
+                self.constant(Value::Void);
+            },
+            Node::UnaryOp(op, inner) => {
+                self.emit(inner);
+                self.code.push(Op::Unary(*op));
+            },
+            Node::BinaryOp(op, lhs, rhs) => {
+                self.emit(lhs);
+                self.emit(rhs);
+                self.code.push(Op::Binary(*op));
+            },
+            Node::List(items) => {
+                items.iter().for_each(|n| self.emit(n));
+                self.code.push(Op::List(items.len()));
+            },
+            Node::Block(items) if !items.is_empty() => {
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        self.code.push(Op::Pop);
+                    }
+                    self.emit(item);
+                }
+            },
+            Node::FunctionCall(func, args) if !matches!(&**func, Node::Identifier(s) if s.starts_with('$'))
+                    && !args.iter().any(|a| matches!(a, Node::Assign(..) | Node::Spread(_))) => {
+                self.emit(func);
+                args.iter().for_each(|n| self.emit(n));
+                self.code.push(Op::Call(args.len()));
+            },
+            Node::Format(parts) => {
+                for part in parts {
+                    match part {
+                        FormatPart::Literal(s) => self.constant(Value::Str(s.to_owned())),
+                        FormatPart::Expr(node, spec) => {
+                            self.emit(node);
+                            self.specs.push(spec.clone());
+                            self.code.push(Op::Format(self.specs.len() - 1));
+                        }
+                    }
+                }
+                self.code.push(Op::Concat(parts.len()));
+            },
+            Node::Compare(first, rest) => {
+                self.emit(first);
+                let mut jumps = vec![];
+                for (i, (op, node)) in rest.iter().enumerate() {
+                    self.emit(node);
+                    if i == rest.len() - 1 {
+                        self.code.push(Op::Compare(*op));
+                    } else {
+                        jumps.push(self.code.len());
+                        self.code.push(Op::CompareOrJump(*op, 0));
+                    }
+                }
+                let end = self.code.len();
+                for j in jumps {
+                    if let Op::CompareOrJump(_, target) = &mut self.code[j] {
+                        *target = end;
+                    }
+                }
+            },
+            _ => {
+                self.nodes.push(node.clone());
+                self.code.push(Op::Tree(self.nodes.len() - 1));
+            }
+        }
+    }
+}
+
+/// Runs a [`Program`], keeping the values of its variables in slots between runs.
+pub struct Vm {
+    program: Program,
+    ctx: Context,
+    slots: Vec<Option<Value>>,
+    /// Slots changed with [`Vm::set`] that have not been written to the context yet
+    pending: Vec<usize>,
+    /// The version of the context when the slots were last loaded from it
+    version: u64,
+    stack: Vec<Value>
+}
+
+impl Vm {
+    /// Create a machine for a program. The context's frames are shared, so assignments made
+    /// by the program are visible in `ctx`, and changes to `ctx` are seen by the next run.
+    pub fn new(program: &Program, ctx: &Context) -> Self {
+        let mut vm = Self {
+            program: program.clone(),
+            ctx: ctx.share(),
+            slots: vec![None; program.0.slots.len()],
+            pending: vec![],
+            version: 0,
+            stack: vec![]
+        };
+        vm.reload();
+        vm
+    }
+
+    /// Set a variable for the following runs. Unlike an assignment, the value is only written
+    /// to the context when something other than the program itself could read it there, such
+    /// as a function call, so setting an input and running a program that only does arithmetic
+    /// does not look up any names.
+    pub fn set(&mut self, slot: usize, value: Value) {
+        self.slots[slot] = Some(value);
+        if !self.pending.contains(&slot) {
+            self.pending.push(slot);
+        }
+    }
+
+    /// The current value of a variable, if it is set.
+    pub fn get(&self, slot: usize) -> Option<&Value> {
+        self.slots[slot].as_ref()
+    }
+
+    /// Load every slot that is not pending from the context
+    fn reload(&mut self) {
+        for (i, name) in self.program.0.slots.iter().enumerate() {
+            if !self.pending.contains(&i) {
+                self.slots[i] = self.ctx.get(name);
+            }
+        }
+        self.version = self.ctx.version();
+    }
+
+    /// Reload the slots if the context changed since they were loaded
+    fn refresh(&mut self) {
+        if self.ctx.version() != self.version {
+            self.reload();
+        }
+    }
+
+    /// Write the pending slots to the context, before running code that may read them there
+    fn flush(&mut self) {
+        self.refresh();
+        for slot in std::mem::take(&mut self.pending) {
+            if let Some(v) = &self.slots[slot] {
+                self.ctx.insert(self.program.0.slots[slot].to_owned(), v.clone());
+            }
+        }
+        self.version = self.ctx.version();
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("stack underflow")
+    }
+
+    /// Run the program, returning the value of the expression.
+    pub fn run(&mut self) -> Result<Value, EvalError> {
+        let _display = crate::format::enter(self.ctx.display_cell());
+        self.refresh();
+        self.stack.clear();
+        let program = self.program.clone();
+        let program = &program.0;
+        let mut pc = 0;
+        while pc < program.code.len() {
+            match &program.code[pc] {
+                Op::Const(i) => self.stack.push(program.consts[*i].clone()),
+                Op::Load(i) => match &self.slots[*i] {
+                    Some(v) => self.stack.push(v.clone()),
+                    None => return Err(EvalErrorKind::VariableUnset(program.slots[*i].to_owned()).into())
+                },
+                Op::Store(i) => {
+                    let v = self.pop();
+                    self.ctx.insert(program.slots[*i].to_owned(), v.clone());
+                    self.slots[*i] = Some(v);
+                    self.pending.retain(|s| s != i);
+                    self.version = self.ctx.version();
+                },
+                Op::Pop => { self.pop(); },
+                Op::Binary(op) => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    self.stack.push(op.eval(lhs, rhs)?);
+                },
+                Op::Unary(op) => {
+                    let val = self.pop();
+                    self.stack.push(op.eval(val)?);
+                },
+                Op::List(n) => {
+                    let items = self.stack.split_off(self.stack.len() - n);
+                    self.stack.push(Value::List(items));
+                },
+                Op::Call(n) => {
+                    let args = self.stack.split_off(self.stack.len() - n);
+                    let func = self.pop();
+                    self.flush();
+                    let res = func.eval(args);
+                    self.refresh();
+                    self.stack.push(res?);
+                },
+                Op::Format(i) => {
+                    let val = self.pop();
+                    self.stack.push(Value::Str(program.specs[*i].format(&val)?));
+                },
+                Op::Concat(n) => {
+                    let mut res = String::new();
+                    for part in self.stack.split_off(self.stack.len() - n) {
+                        if let Value::Str(s) = part {
+                            res += &s;
+                        }
+                    }
+                    self.stack.push(Value::Str(res));
+                },
+                Op::CompareOrJump(op, target) => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    if op.eval(lhs, rhs.clone())? == Value::Bool(true) {
+                        self.stack.push(rhs);
+                    } else {
+                        self.stack.push(Value::Bool(false));
+                        pc = *target;
+                        continue
+                    }
+                },
+                Op::Compare(op) => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    self.stack.push(Value::Bool(op.eval(lhs, rhs)? == Value::Bool(true)));
+                },
+                Op::Tree(i) => {
+                    self.flush();
+                    let res = program.nodes[*i].eval(&mut self.ctx);
+                    self.refresh();
+                    self.stack.push(res?);
+                }
+            }
+            pc += 1;
+        }
+        Ok(self.pop())
+    }
+}
+
+/// A [`Program`] with declared parameters, created by [`Program::bind`].
+pub struct Bound {
+    vm: Vm,
+    /// The context of the machine, whose innermost frame holds the parameters
+    ctx: Context,
+    params: Vec<Param>
+}
+
+enum Param {
+    Slot(usize),
+    /// A parameter that is only used by parts of the program evaluated as a tree, such as
+    /// lambdas, which is assigned in the context instead
+    Name(String)
+}
+
+impl Bound {
+    fn set(&mut self, args: impl ExactSizeIterator<Item=Value>) -> Result<(), EvalError> {
+        bound_args(args.len(), self.params.len(), self.params.len())?;
+        for (param, arg) in self.params.iter().zip(args) {
+            match param {
+                Param::Slot(slot) => self.vm.set(*slot, arg),
+                Param::Name(name) => self.ctx.declare(name.to_owned(), arg)
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the program with the parameters set to `args`, in the order they were declared.
+    pub fn call(&mut self, args: &[Value]) -> Result<Value, EvalError> {
+        self.set(args.iter().cloned())?;
+        self.vm.run()
+    }
+
+    /// Run the program with float arguments, converting the result to a float. Results that
+    /// are not real numbers are errors.
+    pub fn call_f64(&mut self, args: &[f64]) -> Result<f64, EvalError> {
+        self.set(args.iter().map(|x| Value::Float(*x)))?;
+        match self.vm.run()? {
+            Value::Float(f) => Ok(f),
+            Value::Integer(n) => Ok(n as f64),
+            Value::Ratio(r) => Ok(crate::value::r2f64(&r)),
+            v => Err(EvalErrorKind::Other(format!("Expected a real number, found {}", v)).into())
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use complexpr::*;
+
+#[test]
+fn display() {
+    let s = |x: &str| Value::from(x);
+    let mut ctx = ctx_default();
+    assert_eq!(eval("to_str(1/3)", &mut ctx).unwrap(), s(&(1.0f64/3.0).to_string()));
+    eval(r#"set_display(".3")"#, &mut ctx).unwrap();
+    assert_eq!(eval("to_str((1/3, 2, 1//2),)", &mut ctx).unwrap(), s("(0.333, 2, 1//2)"));
+    assert_eq!(eval("to_repr(1.5-0.25i)", &mut ctx).unwrap(), s("1.500 - 0.250i"));
+    assert_eq!(eval(r#"f"{2/3} {2/3:.1} {2/3:>7}""#, &mut ctx).unwrap(), s("0.667 0.7   0.667"));
+    eval(r#"set_display(",.1fp")"#, &mut ctx).unwrap();
+    assert_eq!(format::with_display(&ctx, || format!("{}", Value::from_complex(0.0, -2000.0))), "2,000.0∠-1.6");
+    // outside of evaluation the default is used
+    assert_eq!(format!("{:?}", Value::Float(2.0)), "2.0");
+    assert!(eval(r#"set_display("10.2")"#, &mut ctx).is_err());
+    eval(r#"set_display("")"#, &mut ctx).unwrap();
+    assert_eq!(format::with_display(&ctx, || format!("{:?}", Value::Float(2.0))), "2.0");
+}
+
+#[test]
+fn display_per_context() {
+    let s = |x: &str| Value::from(x);
+    let mut a = ctx_default();
+    let mut b = ctx_default();
+    eval(r#"set_display(".2"); show = x:to_str(x)"#, &mut a).unwrap();
+    assert_eq!(eval("to_str(1/3)", &mut b).unwrap(), s(&(1.0f64/3.0).to_string()));
+    assert_eq!(eval("to_str(1/3)", &mut a.share()).unwrap(), s("0.33"));
+    // lambdas use the setting of the context they were created in
+    let show = eval("show", &mut a).unwrap();
+    b.insert("show".to_owned(), show);
+    assert_eq!(eval("(show(1/3), to_str(1/3))", &mut b).unwrap(), Value::from(vec![s("0.33"), s(&(1.0f64/3.0).to_string())]));
+    // copies have their own setting
+    let c = a.clone();
+    c.set_display(format::FormatSpec::parse(".1").unwrap());
+    assert_eq!(eval("to_str(1/3)", &mut a).unwrap(), s("0.33"));
+    assert_eq!(eval("show(1/3)", &mut c.share()).unwrap(), s("0.3"));
+    assert_eq!(a.display(), format::FormatSpec::parse(".2").unwrap());
+    let handle = std::thread::spawn(|| eval_default("to_str(1/3)").unwrap());
+    assert_eq!(handle.join().unwrap(), s(&(1.0f64/3.0).to_string()));
+}
@@ -1,5 +1,9 @@
 use complexpr::*;
 
+fn assert_close(a: f64, b: f64, tol: f64) {
+    assert!((a - b).abs() < tol, "{} != {}", a, b);
+}
+
 #[test]
 fn ops() {
     assert_eq!(eval_default("add(2, 3.5, 4.2)").unwrap(), Value::from(2.+3.5+4.2));
@@ -32,6 +36,12 @@ fn trig() {
 fn num() {
     assert_eq!(eval_default("min(0.6, 1.4, -0.5)").unwrap(), Value::from(-0.5));
     assert_eq!(eval_default("max(0.6, 1.4, -0.5)").unwrap(), Value::from(1.4));
+    // a single list is spread
+    assert_eq!(eval_default("max((1, 5, 3))").unwrap(), Value::from(5));
+    assert_eq!(eval_default("min((1, 5, 3),)").unwrap(), Value::from(1));
+    assert_eq!(eval_default("max(range(4))").unwrap(), Value::from(3));
+    assert_eq!(eval_default("max((1, 2), (3,))").unwrap(), eval_default("(3,)").unwrap());
+    assert!(eval_default("max(())").is_err());
     assert_eq!(eval_default("abs(-0.6)").unwrap(), Value::from(0.6));
     assert_eq!(eval_default("sqrt(0.6)").unwrap(), Value::from(0.6f64.sqrt()));
     assert!(eval_default("sqrt(-1)").unwrap().as_float().unwrap().is_nan());
@@ -61,3 +71,348 @@ fn complex() {
     assert_eq!(eval_default("norm_sq(1+i)").unwrap(), Value::from(2f64));
     assert_eq!(eval_default("normalize(1+i)").unwrap(), Value::from_complex(1.0/2f64.sqrt(), 1.0/2f64.sqrt()));
 }
+
+#[cfg(feature = "random")]
+#[test]
+fn random() {
+    let mut a = ctx_default_seeded(42);
+    let mut b = ctx_default_seeded(42);
+    let expr = "(random(), random_range(1000), shuffle(range(10)))";
+    assert_eq!(eval(expr, &mut a).unwrap(), eval(expr, &mut b).unwrap());
+    eval("seed(7)", &mut a).unwrap();
+    eval("seed(7)", &mut b).unwrap();
+    assert_eq!(eval(expr, &mut a).unwrap(), eval(expr, &mut b).unwrap());
+}
+
+#[test]
+fn signal() {
+    fn assert_spectrum(expr: &str, expected: &[(f64, f64)]) {
+        let res = eval_default(expr).unwrap();
+        let res = res.as_list().unwrap();
+        assert_eq!(res.len(), expected.len(), "{}", expr);
+        for (v, &(re, im)) in res.iter().zip(expected) {
+            let c = v.as_complex().unwrap();
+            assert_close(c.re, re, 1e-9);
+            assert_close(c.im, im, 1e-9);
+        }
+    }
+    assert_spectrum("fft((1, 2, 3, 4),)", &[(10., 0.), (-2., 2.), (-2., 0.), (-2., -2.)]);
+    assert_spectrum("fft((1, 2, 3, 4, 5),)", &[(15., 0.), (-2.5, 3.440954801177933), (-2.5, 0.8122992405822659),
+        (-2.5, -0.8122992405822659), (-2.5, -3.440954801177933)]);
+    assert_spectrum("ifft(fft((1, 2i, 3, 4, 5, 6),))", &[(1., 0.), (0., 2.), (3., 0.), (4., 0.), (5., 0.), (6., 0.)]);
+    assert_spectrum("dft((1, 2, 3),)", &[(6., 0.), (-1.5, 0.8660254037844386), (-1.5, -0.8660254037844386)]);
+    assert_spectrum("rfft((1, 2, 3, 4),)", &[(10., 0.), (-2., 2.), (-2., 0.)]);
+    assert_spectrum("convolve((1, 2, 3), (0, 1, 0.5))", &[(0., 0.), (1., 0.), (2.5, 0.), (4., 0.), (1.5, 0.)]);
+    assert_spectrum("correlate((1, 2, 3), (0, 1, 0.5))", &[(0.5, 0.), (2., 0.), (3.5, 0.), (3., 0.), (0., 0.)]);
+    assert_eq!(eval_default("fftfreq(4)").unwrap(), eval_default("(0, 0.25, -0.5, -0.25)").unwrap());
+    assert_eq!(eval_default("hann(3)").unwrap(), eval_default("(0, 1, 0)").unwrap());
+}
+
+#[test]
+fn interp() {
+    let approx = |expr: &str, expected| assert_close(eval_default(expr).unwrap().as_float().unwrap(), expected, 1e-9);
+    assert_eq!(eval_default("lerp(2, 4, 0.25)").unwrap(), Value::from(2.5));
+    assert_eq!(eval_default("lerp(0, 1//2, 1//2)").unwrap(), Value::from_ratio(1, 4));
+    approx("interp_linear((0, 1, 3), (0, 10, 30), 2)", 20.);
+    approx("interp_linear((0, 1, 3), (0, 10, 30), -1)", 0.);
+    approx("interp_linear((0, 1, 3), (0, 10, 30), 5)", 30.);
+    approx("spline((0, 1, 2, 3), (0, 1, 8, 27))(2)", 8.);
+    approx("spline((0, 1, 2), (0, 1, 0))(0.5)", 0.6875);
+    approx("lagrange((0, 1, 2), (1, 3, 7))(3)", 13.);
+    let coeffs = eval_default("polyfit((0, 1, 2, 3, 4), (1, 3, 7, 13, 21), 2)").unwrap();
+    for (c, expected) in coeffs.as_list().unwrap().iter().zip(&[1., 1., 1.]) {
+        assert_close(c.as_float().unwrap(), *expected, 1e-9);
+    }
+    assert_eq!(eval_default("polyval((1, 2, 3), 2)").unwrap(), Value::from(17));
+}
+
+#[test]
+fn optimize() {
+    let approx = |val: &Value, expected| assert_close(val.as_float().unwrap(), expected, 1e-5);
+    let res = eval_default("minimize(x:((x-3)^2+1), 0)").unwrap();
+    approx(&res.as_list().unwrap()[0], 3.);
+    approx(&res.as_list().unwrap()[1], 1.);
+    let res = eval_default("minimize(x:cos(x), 2, 4)").unwrap();
+    approx(&res.as_list().unwrap()[0], std::f64::consts::PI);
+    approx(&res.as_list().unwrap()[1], -1.);
+    let res = eval_default("maximize(x:sin(x), 0, 3)").unwrap();
+    approx(&res.as_list().unwrap()[0], std::f64::consts::FRAC_PI_2);
+    for f in &["minimize", "minimize_bfgs"] {
+        let res = eval_default(&format!("{}((x, y):((1-x)^2 + 100*(y-x^2)^2), (-1, 2))", f)).unwrap();
+        let argmin = res.as_list().unwrap()[0].as_list().unwrap().clone();
+        approx(&argmin[0], 1.);
+        approx(&argmin[1], 1.);
+    }
+    let res = eval_default("maximize_bfgs((x, y):(5-(x-2)^2-(y-1)^2), (0, 0))").unwrap();
+    approx(&res.as_list().unwrap()[1], 5.);
+    assert!(eval_default("minimize(x:x, 0)").is_err());
+    // the line search stalls at the jump, where the gradient is far from zero
+    let err = eval_default("minimize_bfgs((x, y):((x < 0)(-x, x + 1) + y^2), (-1, 0))").unwrap_err();
+    assert!(err.to_string().contains("Failed to converge"), "{}", err);
+}
+
+#[test]
+fn string() {
+    let s = |x: &str| Value::from(x);
+    assert_eq!(eval_default(r#"split("a,b,,c", ",")"#).unwrap(), Value::from(vec![s("a"), s("b"), s(""), s("c")]));
+    assert_eq!(eval_default(r#"join((1, "b", 2.5), "-")"#).unwrap(), s("1-b-2.5"));
+    assert_eq!(eval_default(r#"replace("aXbXc", "X", "--")"#).unwrap(), s("a--b--c"));
+    assert_eq!(eval_default(r#"trim("  hi \n")"#).unwrap(), s("hi"));
+    assert_eq!(eval_default(r#"upper("straße")"#).unwrap(), s("STRASSE"));
+    assert_eq!(eval_default(r#"lower("ABC")"#).unwrap(), s("abc"));
+    assert_eq!(eval_default(r#"starts_with("hello", "he")"#).unwrap(), Value::from(true));
+    assert_eq!(eval_default(r#"ends_with("hello", "he")"#).unwrap(), Value::from(false));
+    assert_eq!(eval_default(r#"contains("hello", "ll")"#).unwrap(), Value::from(true));
+    assert_eq!(eval_default(r#"find("héllo", "l")"#).unwrap(), Value::from(2));
+    assert_eq!(eval_default(r#"find("hello", "z")"#).unwrap(), Value::Void);
+    assert_eq!(eval_default(r#"pad_left("7", 3, "0")"#).unwrap(), s("007"));
+    assert_eq!(eval_default(r#"pad_right("é", 3)"#).unwrap(), s("é  "));
+    assert_eq!(eval_default(r#"lines("a\r\nb\nc")"#).unwrap(), Value::from(vec![s("a"), s("b"), s("c")]));
+    assert_eq!(eval_default(r#"words(" a  b	c ")"#).unwrap(), Value::from(vec![s("a"), s("b"), s("c")]));
+    assert_eq!(eval_default(r#"repeat("ab", 3)"#).unwrap(), s("ababab"));
+    assert_eq!(eval_default(r#"format("{} + {} = {{{}}}", 1, 2, 3)"#).unwrap(), s("1 + 2 = {3}"));
+    assert!(eval_default(r#"format("{} {}", 1)"#).is_err());
+    assert_eq!(eval_default(r#"len("héllo")"#).unwrap(), Value::from(5));
+    assert_eq!(eval_default(r#"index("héllo", 4)"#).unwrap(), s("o"));
+    assert_eq!(eval_default(r#"slice("héllo", 1, 3)"#).unwrap(), s("él"));
+    assert_eq!(eval_default(r#"first("héllo", 2)"#).unwrap(), s("hé"));
+}
+
+#[test]
+fn regex() {
+    let s = |x: &str| Value::from(x);
+    assert_eq!(eval_default(r#"re_match("^[a-z]+\\d$", "abc1")"#).unwrap(), Value::from(true));
+    assert_eq!(eval_default(r#"re_match("^\\d+$", "12a")"#).unwrap(), Value::from(false));
+    assert_eq!(eval_default(r#"re_find_all("\\d+", "a1b22c333")"#).unwrap(), Value::from(vec![s("1"), s("22"), s("333")]));
+    assert_eq!(eval_default(r#"re_captures("(\\w+)@(\\w+)?", "me@")"#).unwrap(), Value::from(vec![s("me@"), s("me"), Value::Void]));
+    assert_eq!(eval_default(r#"re_captures("\\d", "abc")"#).unwrap(), Value::Void);
+    assert_eq!(eval_default(r#"re_named("(?P<key>\\w+)=(?P<val>\\w+)", "x=5")"#).unwrap(),
+        Value::from(vec![Value::from(vec![s("key"), s("x")]), Value::from(vec![s("val"), s("5")])]));
+    assert_eq!(eval_default(r#"re_replace("(\\w+)=(\\w+)", "a=1 b=2", "$2=$1")"#).unwrap(), s("1=a 2=b"));
+    assert_eq!(eval_default(r#"re_replace("\\d+", "a1b22", m:(len(index(m, 0))))"#).unwrap(), s("a1b2"));
+    assert_eq!(eval_default(r#"re_split(",\\s*", "a, b,c")"#).unwrap(), Value::from(vec![s("a"), s("b"), s("c")]));
+    assert_eq!(eval_default(r#"r = re_compile("o+"); map(("foo", "boo"), x:(re_find_all(r, x)))"#).unwrap(),
+        Value::from(vec![Value::from(vec![s("oo")]), Value::from(vec![s("oo")])]));
+    assert!(eval_default(r#"re_match("(", "x")"#).is_err());
+}
+
+#[test]
+fn format_num() {
+    let s = |x: &str| Value::from(x);
+    assert_eq!(eval_default(r#"format_num(1234567.891, ",.2f")"#).unwrap(), s("1,234,567.89"));
+    assert_eq!(eval_default(r#"format_num(-1234567, ",")"#).unwrap(), s("-1,234,567"));
+    assert_eq!(eval_default(r#"format_num(0.5, "f")"#).unwrap(), s("0.500000"));
+    assert_eq!(eval_default(r#"format_num(12345.678, ".3e")"#).unwrap(), s("1.235e4"));
+    assert_eq!(eval_default(r#"format_num(12345.678, "n")"#).unwrap(), s("12.345678e3"));
+    assert_eq!(eval_default(r#"format_num(0.01, "n")"#).unwrap(), s("10e-3"));
+    assert_eq!(eval_default(r#"format_num(-0.000123456, ".2n")"#).unwrap(), s("-123e-6"));
+    assert_eq!(eval_default(r#"format_num(pi, ".4g")"#).unwrap(), s("3.142"));
+    assert_eq!(eval_default(r#"format_num(123456789, ".3g")"#).unwrap(), s("1.23e8"));
+    assert_eq!(eval_default(r#"format_num(1//3, ".3")"#).unwrap(), s("0.333"));
+    assert_eq!(eval_default(r#"format_num(1-2i, ".1")"#).unwrap(), s("1.0 - 2.0i"));
+    assert_eq!(eval_default(r#"format_num(2i, ".3p")"#).unwrap(), s("2.000∠1.571"));
+    assert_eq!(eval_default(r#"format_num(3.5, "+08.2f")"#).unwrap(), s("+0003.50"));
+    assert_eq!(eval_default(r#"to_str(1-2i)"#).unwrap(), s("1 - 2i"));
+    assert_eq!(eval_default(r#"to_repr(1.5+2i)"#).unwrap(), s("1.5 + 2.0i"));
+    assert!(eval_default(r#"format_num("x", ".2")"#).is_err());
+    assert!(eval_default(r#"format_num(1.5, "x")"#).is_err());
+    assert!(eval_default(r#"format_num(1, ".2q")"#).is_err());
+    assert_eq!(eval_default(r#"len(format_num(1, "65535"))"#).unwrap(), Value::from(65535));
+    assert!(eval_default(r#"format_num(1, "65536")"#).is_err());
+    assert!(eval_default(r#"format_num(1, "10000000000")"#).is_err());
+    for spec in [".70000", ".70000g", ".70000n", ".65536f", ".1000000000"] {
+        assert!(eval_default(&format!(r#"format_num(1.5, "{}")"#, spec)).is_err(), "{}", spec);
+    }
+    // too many digits after the point for fixed notation, so scientific
+    assert_eq!(eval_default(r#"len(format_num(0.0001, ".65535g"))"#).unwrap(), Value::from(65539));
+}
+
+#[test]
+fn lists() {
+    let l = |x: Vec<i64>| Value::from(x.into_iter().map(Value::from).collect::<Vec<Value>>());
+    let s = |x: &str| Value::from(x);
+    assert_eq!(eval_default(r#"sort_by(("ccc", "a", "bb", "d"), len)"#).unwrap(), Value::from(vec![s("a"), s("d"), s("bb"), s("ccc")]));
+    assert_eq!(eval_default("sort_with((3, 1, 2), (a, b):(b - a))").unwrap(), l(vec![3, 2, 1]));
+    assert_eq!(eval_default("flatten((1, (2, (3,)), (4,)),)").unwrap(), Value::from(vec![1.into(), 2.into(), l(vec![3]), 4.into()]));
+    assert_eq!(eval_default("flatten((1, (2, (3,)), (4,)), 2)").unwrap(), l(vec![1, 2, 3, 4]));
+    assert_eq!(eval_default("unique((1, 2, 1.0, 3, 2),)").unwrap(), l(vec![1, 2, 3]));
+    assert_eq!(eval_default("group_by((1, 2, 3, 4, 5), x:(x % 2))").unwrap(),
+        Value::from(vec![Value::from(vec![1.into(), l(vec![1, 3, 5])]), Value::from(vec![0.into(), l(vec![2, 4])])]));
+    assert_eq!(eval_default("partition((1, 2, 3, 4), x:(x > 2))").unwrap(), Value::from(vec![l(vec![3, 4]), l(vec![1, 2])]));
+    assert_eq!(eval_default("any((1, 2, 3), x:(x > 2))").unwrap(), Value::from(true));
+    assert_eq!(eval_default("all((1, 2, 3), x:(x > 2))").unwrap(), Value::from(false));
+    assert_eq!(eval_default("all((true, true),)").unwrap(), Value::from(true));
+    assert_eq!(eval_default("find((1, 4, 9, 16), x:(x > 5))").unwrap(), Value::from(9));
+    assert_eq!(eval_default("find((1, 4), x:(x > 5))").unwrap(), Value::Void);
+    assert_eq!(eval_default("position((1, 4, 9, 16), x:(x > 5))").unwrap(), Value::from(2));
+    assert_eq!(eval_default("contains((1, 2, 3), 2.0)").unwrap(), Value::from(true));
+    assert_eq!(eval_default(r#"contains("hello", "ell")"#).unwrap(), Value::from(true));
+    assert_eq!(eval_default("take_while((1, 2, 5, 1), x:(x < 3))").unwrap(), l(vec![1, 2]));
+    assert_eq!(eval_default("drop_while((1, 2, 5, 1), x:(x < 3))").unwrap(), l(vec![5, 1]));
+    assert_eq!(eval_default("scan((1, 2, 3, 4), add)").unwrap(), l(vec![1, 3, 6, 10]));
+    assert_eq!(eval_default("scan(10, (1, 2, 3), add)").unwrap(), l(vec![11, 13, 16]));
+    assert_eq!(eval_default("chunks((1, 2, 3, 4, 5), 2)").unwrap(), Value::from(vec![l(vec![1, 2]), l(vec![3, 4]), l(vec![5])]));
+    assert_eq!(eval_default("windows((1, 2, 3), 2)").unwrap(), Value::from(vec![l(vec![1, 2]), l(vec![2, 3])]));
+    assert_eq!(eval_default("transpose(((1, 2, 3), (4, 5, 6)),)").unwrap(), Value::from(vec![l(vec![1, 4]), l(vec![2, 5]), l(vec![3, 6])]));
+    assert!(eval_default("transpose(((1, 2), (3,)),)").is_err());
+    assert_eq!(eval_default("count((1, 2, 1, 3), 1)").unwrap(), Value::from(2));
+    assert_eq!(eval_default("count((1, 2, 1, 3), x:(x > 1))").unwrap(), Value::from(2));
+    assert_eq!(eval_default("count((true, false, true), true)").unwrap(), Value::from(2));
+    assert_eq!(eval_default("count((1, 2.5, 3), is_int)").unwrap(), Value::from(2));
+    let mut strings = Context::new();
+    for (k, v) in complexpr::function::string::CTX_ALL.iter() {
+        strings.insert(k, v);
+    }
+    assert_eq!(eval(r#"(contains("abc", "bc"), find("abc", "c"), contains((1, 2), 2))"#, &mut strings).unwrap(),
+        Value::from(vec![Value::Bool(true), Value::from(2), Value::Bool(true)]));
+    assert_eq!(eval_default(r#"min_by(("bb", "a", "c"), len)"#).unwrap(), s("a"));
+    assert_eq!(eval_default(r#"max_by(("bb", "a", "cc"), len)"#).unwrap(), s("bb"));
+    assert_eq!(eval_default("last((1, 2, 3), 2)").unwrap(), l(vec![2, 3]));
+    assert_eq!(eval_default(r#"last("héllo", 4)"#).unwrap(), s("éllo"));
+}
+
+#[test]
+fn sort_total() {
+    let sorted = eval_default(r#"sort((3, "b", 0.0/0.0, 1//2, (1, 2), -1.5, true, 2i, "a", (1,), 1),)"#).unwrap();
+    let repr = format!("{:?}", sorted);
+    assert_eq!(repr, r#"(true, -1.5, 0.0 + 2.0i, 1//2, 1, 3, NaN, "a", "b", (1), (1, 2))"#);
+    assert_eq!(eval_default("sort_by((3, 0.0/0.0, 1), x:x)").unwrap().to_string(), "(1, 3, NaN)");
+    // stable: equal elements keep their order
+    assert_eq!(eval_default("sort_by(((1, 2), (0, 3), (1, 1), (0, 4)), x:index(x, 0))").unwrap(),
+        eval_default("((0, 3), (0, 4), (1, 2), (1, 1))").unwrap());
+    assert_eq!(eval_default("len(sort(rev(range(100000))))").unwrap(), Value::from(100000));
+    assert_eq!(eval_default("cmp_total(1, 1.0)").unwrap(), Value::from(0));
+    assert_eq!(eval_default(r#"cmp_total("a", 5)"#).unwrap(), Value::from(1));
+    assert_eq!(eval_default("cmp_total(0.0/0.0, 1.0/0.0)").unwrap(), Value::from(1));
+    // mixed numbers are compared exactly, not as floats
+    assert_eq!(eval_default("cmp_total(9007199254740993, 9007199254740992.0)").unwrap(), Value::from(1));
+    assert_eq!(eval_default("cmp_total(9007199254740992.0, 9007199254740992)").unwrap(), Value::from(0));
+    assert_eq!(eval_default("cmp_total(1//3, 0.3333333333333333)").unwrap(), Value::from(1));
+    assert_eq!(eval_default("cmp_total(-1//3, -0.3333333333333333)").unwrap(), Value::from(-1));
+    assert_eq!(eval_default("cmp_total(1//3, 2//5)").unwrap(), Value::from(-1));
+    assert_eq!(eval_default("cmp_total(-1, -0.5 + 0i)").unwrap(), Value::from(-1));
+    assert_eq!(eval_default("cmp_total(9223372036854775807, 9223372036854775807.0)").unwrap(), Value::from(-1));
+    assert_eq!(eval_default("cmp_total(0, -0.0)").unwrap(), Value::from(0));
+    assert_eq!(eval_default("cmp_total(0, -1.0/0.0)").unwrap(), Value::from(1));
+    let sorted = eval_default("sort((9007199254740993, 9007199254740992.0, 9007199254740992),)").unwrap();
+    assert_eq!(format!("{:?}", sorted), "(9007199254740992.0, 9007199254740992, 9007199254740993)");
+}
+
+#[test]
+fn lazy() {
+    let l = |x: Vec<i64>| Value::from(x.into_iter().map(Value::from).collect::<Vec<Value>>());
+    assert_eq!(eval_default("first(range(10^9), 5)").unwrap(), l(vec![0, 1, 2, 3, 4]));
+    assert_eq!(eval_default("collect(range(5, 0, -2))").unwrap(), l(vec![5, 3, 1]));
+    assert_eq!(eval_default("len(range(1000000))").unwrap(), Value::from(1000000));
+    assert_eq!(eval_default("collect(take(generate(x:(x*2), 1), 5))").unwrap(), l(vec![1, 2, 4, 8, 16]));
+    assert_eq!(eval_default("first(cycle((1, 2),), 5)").unwrap(), l(vec![1, 2, 1, 2, 1]));
+    assert_eq!(eval_default("first(filter(map(count_from(1), x:(x^2)), x:(x % 2 == 1)), 3)").unwrap(), l(vec![1, 9, 25]));
+    assert_eq!(eval_default("collect(take_while(count_from(1, 3), x:(x < 10)))").unwrap(), l(vec![1, 4, 7]));
+    assert_eq!(eval_default("collect(zip(count_from(10), (1, 2)))").unwrap(), Value::from(vec![l(vec![10, 1]), l(vec![11, 2])]));
+    assert_eq!(eval_default("index(enumerate(range(5, 10)), 2)").unwrap(), l(vec![2, 7]));
+    assert_eq!(eval_default("index(count_from(0, 7), 1000)").unwrap(), Value::from(7000));
+    assert_eq!(eval_default("find(count_from(1), x:(x^2 > 50))").unwrap(), Value::from(8));
+    assert_eq!(eval_default("fold(range(101), add)").unwrap(), Value::from(5050));
+    assert_eq!(eval_default("to_str(range(3))").unwrap(), Value::from("<iterator>"));
+    assert!(eval_default("collect(count_from(0))").is_err());
+    assert!(eval_default("len(cycle((1,),))").is_err());
+    assert!(eval_default("range(0, 5, 0)").is_err());
+    assert!(eval_default("range(2.5)").is_err());
+    assert!(eval_default("first(map(range(5), x:(x/0)), 2)").is_err());
+    // sequences compare equal to lists and are collected when added to them
+    assert_eq!(eval_default("range(3) == (0, 1, 2)").unwrap(), Value::Bool(true));
+    assert_eq!(eval_default("(0, 1, 2) == map(range(3), x:x)").unwrap(), Value::Bool(true));
+    assert_eq!(eval_default("range(3) == (0, 1)").unwrap(), Value::Bool(false));
+    assert_eq!(eval_default("range(3) != (0, 1, 2, 3)").unwrap(), Value::Bool(true));
+    assert_eq!(eval_default("count_from(0) == (0, 1)").unwrap(), Value::Bool(false));
+    assert_eq!(eval_default("range(3) + (3,)").unwrap(), l(vec![0, 1, 2, 3]));
+    assert_eq!(eval_default("(-1,) + range(2)").unwrap(), l(vec![-1, 0, 1]));
+    assert!(eval_default("(1,) + count_from(0)").is_err());
+}
+
+#[test]
+fn bits() {
+    assert_eq!(eval_default(r"7 \\ 2").unwrap(), Value::from(3));
+    assert_eq!(eval_default(r"-7 \\ 2").unwrap(), Value::from(-4));
+    assert_eq!(eval_default(r"7 \\ -2").unwrap(), Value::from(-4));
+    assert_eq!(eval_default(r"7.5 \\ 2").unwrap(), Value::from(3.0));
+    assert_eq!(eval_default(r"(7//2) \\ (1//3)").unwrap(), Value::from(10));
+    assert_eq!(eval_default("div_floor(-9, 4)").unwrap(), Value::from(-3));
+    assert!(eval_default(r"1 \\ 0").is_err());
+    assert_eq!(eval_default("12 & 10").unwrap(), Value::from(8));
+    assert_eq!(eval_default("12 | 3").unwrap(), Value::from(15));
+    assert_eq!(eval_default("12 xor 10").unwrap(), Value::from(6));
+    assert_eq!(eval_default("true xor true").unwrap(), Value::from(false));
+    assert_eq!(eval_default("1 << 4 + 1").unwrap(), Value::from(32));
+    assert_eq!(eval_default("-16 >> 2").unwrap(), Value::from(-4));
+    assert_eq!(eval_default("~5").unwrap(), Value::from(-6));
+    assert_eq!(eval_default("~~5").unwrap(), Value::from(5));
+    assert_eq!(eval_default("--5").unwrap(), Value::from(5));
+    assert_eq!(eval_default("1 | 2 & 3 == 3").unwrap(), Value::from(true));
+    assert!(eval_default("1 << 64").is_err());
+    assert!(eval_default("1.5 & 1").is_err());
+    assert_eq!(eval_default("popcount(255)").unwrap(), Value::from(8));
+    assert_eq!(eval_default("popcount(-1)").unwrap(), Value::from(64));
+    assert_eq!(eval_default("leading_zeros(1)").unwrap(), Value::from(63));
+    assert_eq!(eval_default("trailing_zeros(40)").unwrap(), Value::from(3));
+    assert_eq!(eval_default("bit_get(5, 2)").unwrap(), Value::from(true));
+    assert_eq!(eval_default("bit_set(5, 1)").unwrap(), Value::from(7));
+    assert_eq!(eval_default("bit_set(5, 0, false)").unwrap(), Value::from(4));
+    assert!(eval_default("bit_get(5, 64)").is_err());
+}
+
+#[test]
+fn refs() {
+    let mut ctx = complexpr::ctx_default();
+    eval("r = ref(1); s = r; s := 5", &mut ctx).unwrap();
+    assert_eq!(eval("deref(r)", &mut ctx).unwrap(), Value::from(5));
+    assert_eq!(eval("r == s", &mut ctx).unwrap(), Value::from(true));
+    assert_eq!(eval("r == ref(5)", &mut ctx).unwrap(), Value::from(false));
+    assert_eq!(eval("update(r, add, 2)", &mut ctx).unwrap(), Value::from(7));
+    assert_eq!(eval("(typeof(r), is_ref(r), is_ref(7))", &mut ctx).unwrap(),
+        Value::from(vec![Value::from("ref"), Value::from(true), Value::from(false)]));
+    assert_eq!(eval("to_str(ref((1, ref())))", &mut ctx).unwrap(), Value::from("ref((1, ref()))"));
+    // state kept across calls of a lambda
+    eval("make_gen = step:(let c = ref(0); :update(c, add, step)); g = make_gen(3)", &mut ctx).unwrap();
+    assert_eq!(eval("(g(), g(), g())", &mut ctx).unwrap(),
+        Value::from(vec![Value::from(3), Value::from(6), Value::from(9)]));
+    eval("calls = ref(0); seen = ref(()); sq = n:(
+        let hit = find(deref(seen), ((k, v),):(k == n));
+        (hit == (;))(:(update(calls, add, 1); seen := deref(seen) + ((n, n^2),); n^2), :index(hit, 1))()
+    )", &mut ctx).unwrap();
+    assert_eq!(eval("(sq(4), sq(4), sq(5), deref(calls))", &mut ctx).unwrap(),
+        Value::from(vec![Value::from(16), Value::from(16), Value::from(25), Value::from(2)]));
+    assert!(eval("1 := 2", &mut ctx).is_err());
+    assert!(eval("deref(1)", &mut ctx).is_err());
+}
+
+#[test]
+fn memoize() {
+    let mut ctx = complexpr::ctx_default();
+    eval("calls = ref(0); fib = memoize(n:(update(calls, add, 1); (n < 2)(:n, :(fib(n - 1) + fib(n - 2)))()))", &mut ctx).unwrap();
+    // filling the cache in steps keeps the recursion shallow
+    assert_eq!(eval("fib(15); fib(30)", &mut ctx).unwrap(), Value::from(832040));
+    assert_eq!(eval("deref(calls)", &mut ctx).unwrap(), Value::from(31));
+    // equal numbers of different types have separate entries
+    assert_eq!(eval("fib(10.0); deref(calls)", &mut ctx).unwrap(), Value::from(42));
+    assert_eq!(eval("cache_clear(fib)", &mut ctx).unwrap(), Value::from(42));
+    assert_eq!(eval("fib(3); deref(calls)", &mut ctx).unwrap(), Value::from(46));
+    // a memoized function returns what the function returns
+    assert_eq!(eval("mi = memoize(is_int); (mi(1), mi(1.0))", &mut ctx).unwrap(),
+        eval("(true, false)", &mut ctx).unwrap());
+    assert_eq!(eval("mx = memoize(x:x); mx(9007199254740992.0); mx(9007199254740993)", &mut ctx).unwrap(),
+        Value::from(9007199254740993));
+    assert_eq!(format!("{:?}", eval("mx((1, 2.0)); mx((1.0, 2))", &mut ctx).unwrap()), "(1.0, 2)");
+    // 0.0 and -0.0 have separate entries
+    assert_eq!(format!("{:?}", eval("(mx(0.0), mx(-0.0), mx(0.0i), mx(-0.0i))", &mut ctx).unwrap()),
+        "(0.0, -0.0, 0.0 + 0.0i, -0.0 - 0.0i)");
+    assert_eq!(eval("inv = memoize(x:(1/x)); (inv(0.0), inv(-0.0))", &mut ctx).unwrap().to_string(), "(inf, -inf)");
+    eval("calls := 0; sq = memoize(x:(update(calls, add, 1); x^2), 2)", &mut ctx).unwrap();
+    assert_eq!(eval("sq(1); sq(2); sq(1); sq(3); sq(1); deref(calls)", &mut ctx).unwrap(), Value::from(3));
+    assert_eq!(eval("sq(2); deref(calls)", &mut ctx).unwrap(), Value::from(4));
+    // arguments that cannot be compared are not cached
+    assert_eq!(eval("sq(nan); sq(nan); deref(calls)", &mut ctx).unwrap(), Value::from(6));
+    assert_eq!(eval("m = memoize((f, x):f(x)); m(sin, 0) + m(cos, 0)", &mut ctx).unwrap(), Value::from(1.0));
+    assert!(eval("cache_clear(sin)", &mut ctx).is_err());
+    assert!(eval("memoize(1)", &mut ctx).is_err());
+}
@@ -0,0 +1,75 @@
+use complexpr::*;
+use complexpr::limits::{Limits, Limit, with_limits};
+use std::time::{Duration, Instant};
+
+fn limit_of(res: Result<Value, Error>) -> Option<Limit> {
+    match res {
+        Err(Error::Eval(function::EvalError{kind: function::EvalErrorKind::LimitExceeded(limit), ..})) => Some(limit),
+        _ => None
+    }
+}
+
+#[test]
+fn steps_and_time() {
+    let mut ctx = ctx_default();
+    let steps = Limits { steps: Some(10_000), ..Default::default() };
+    assert_eq!(limit_of(eval_limited("loop(:1)", &mut ctx, steps.clone())), Some(Limit::Steps(10_000)));
+    assert_eq!(limit_of(eval_limited("iter_while(x:(x+1), 0, x:true)", &mut ctx, steps.clone())), Some(Limit::Steps(10_000)));
+    assert_eq!(limit_of(eval_limited("iter_while(abs, 1, abs)", &mut ctx, steps.clone())), Some(Limit::Steps(10_000)));
+    assert_eq!(limit_of(eval_limited("len(range(10^9))", &mut ctx, steps.clone())), Some(Limit::Steps(10_000)));
+    // builtin callees and builtins that loop on their own are counted too
+    for expr in ["iter(sin, 1, 1000000000)", "factors(9223372036854775783)", "index(range(10^18), 100000000000000000)",
+            "first(range(10^18), 100000000000000000)"] {
+        assert_eq!(limit_of(eval_limited(expr, &mut ctx, steps.clone())), Some(Limit::Steps(10_000)), "{}", expr);
+    }
+    assert_eq!(eval_limited("fold(range(100), add)", &mut ctx, steps).unwrap(), Value::Integer(4950));
+    let time = Limits { time: Some(Duration::from_millis(50)), ..Default::default() };
+    for expr in ["loop(:1)", "iter(sin, 1, 1000000000)"] {
+        let start = Instant::now();
+        assert_eq!(limit_of(eval_limited(expr, &mut ctx, time.clone())), Some(Limit::Time(Duration::from_millis(50))));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
+
+#[test]
+fn depth() {
+    let mut ctx = ctx_default();
+    let limits = Limits { depth: Some(20), ..Default::default() };
+    eval("f = n:(f(n + 1)); g = n:((n <= 0)(:0, :(1 + g(n - 1)))())", &mut ctx).unwrap();
+    let err = eval_limited("f(0)", &mut ctx, limits.clone()).unwrap_err();
+    assert!(err.to_string().ends_with("Evaluation limit exceeded: call depth over 20"), "{}", err);
+    assert_eq!(eval_limited("g(5)", &mut ctx, limits.clone()).unwrap(), Value::Integer(5));
+    // the depth is back to zero after a call returns or fails
+    assert_eq!(eval_limited("map(range(8), g) |> collect |> len", &mut ctx, limits.clone()).unwrap(), Value::Integer(8));
+    assert_eq!(eval_limited("h = :f(0); g(3)", &mut ctx, limits).unwrap(), Value::Integer(3));
+}
+
+#[test]
+fn list_len() {
+    let mut ctx = ctx_default();
+    let limits = Limits { list_len: Some(1000), ..Default::default() };
+    ctx.insert("s".to_owned(), Value::Str("a".repeat(1001)));
+    for expr in ["collect(range(10^9))", "repeat((1, 2), 600)", "l = collect(range(600)); l + l", "seq(x:x, 0, 1001)",
+            "first(range(10^9), 1001)", "chars(s)", r#"len(chars(repeat("a", 10000000)))"#, r#"repeat("ab", 501)"#,
+            r#"pad_left("a", 1001)"#, r#"pad_right("a", 1000000000000)"#, r#"f"{1:2000}""#, r#"f"{1:02000}""#,
+            r#"format_num(1, "<2000")"#] {
+        assert_eq!(limit_of(eval_limited(expr, &mut ctx, limits.clone())), Some(Limit::ListLength(1000)), "{}", expr);
+    }
+    assert_eq!(eval_limited(r#"len(chars(repeat("ab", 500)))"#, &mut ctx, limits.clone()).unwrap(), Value::Integer(1000));
+    assert_eq!(eval_limited("len(repeat((1, 2), 500))", &mut ctx, limits).unwrap(), Value::Integer(1000));
+}
+
+#[test]
+fn scoped() {
+    let mut ctx = ctx_default();
+    let node = compile("fold(range(2000), add)").unwrap();
+    let outer = Limits { steps: Some(100), ..Default::default() };
+    let res = with_limits(outer, || {
+        let inner = with_limits(Limits::default(), || node.eval(&mut ctx.clone()));
+        (inner, node.eval(&mut ctx.clone()))
+    });
+    assert_eq!(res.0.unwrap(), Value::Integer(1999000));
+    assert!(res.1.is_err());
+    // no limits outside with_limits
+    assert_eq!(node.eval(&mut ctx).unwrap(), Value::Integer(1999000));
+}
@@ -36,3 +36,273 @@ fn test_eval() {
     eval("partial = (f, a):(b:f(a, b)); add_one = partial(add, 1)", &mut ctx).unwrap();
     assert_eq!(eval("add_one(5)", &mut ctx).unwrap(), Value::Integer(6));
 }
+
+#[test]
+fn test_fstring() {
+    use complexpr::*;
+    let mut ctx = complexpr::ctx_default();
+    let s = |x: &str| Value::from(x);
+    eval("x = 3; name = \"pi\"", &mut ctx).unwrap();
+    assert_eq!(eval(r#"f"x*2 = {x*2}""#, &mut ctx).unwrap(), s("x*2 = 6"));
+    assert_eq!(eval(r#"f"{name} ~ {pi:.3}, {{braces}}\n""#, &mut ctx).unwrap(), s("pi ~ 3.142, {braces}\n"));
+    assert_eq!(eval(r#"f"[{x:>4}|{name:*^6}|{x:+05}]""#, &mut ctx).unwrap(), s("[   3|**pi**|+0003]"));
+    assert_eq!(eval(r#"f"{255:x} {255:X} {-5:b} {8:o} {1500:.2e}""#, &mut ctx).unwrap(), s("ff FF -101 10 1.50e3"));
+    assert_eq!(eval(r#"f"{join(("a", "b"), ":")}{f"{x}"}""#, &mut ctx).unwrap(), s("a:b3"));
+    assert_eq!(eval(r#"f"\u{41}{1//2:.2}""#, &mut ctx).unwrap(), s("A0.50"));
+    assert!(token::tokenize(r#"f"{x"#).is_err());
+    assert!(token::tokenize(r#"f"{x:q}""#).is_err());
+    assert!(token::tokenize(r#"f"{1:10000000000}""#).is_err());
+    assert!(token::tokenize(r#"f"{1.0:.1000000000}""#).is_err());
+    assert!(token::tokenize(r#"f"{}""#).is_err());
+    assert!(eval(r#"f"{name:x}""#, &mut ctx).is_err());
+}
+
+#[test]
+fn test_destructure() {
+    use complexpr::*;
+    let mut ctx = complexpr::ctx_default();
+    let l = |x: Vec<i64>| Value::from(x.into_iter().map(Value::from).collect::<Vec<Value>>());
+    eval("(a, b) = (1, 2)", &mut ctx).unwrap();
+    assert_eq!(eval("(a, b) = (b, a); a*10 + b", &mut ctx).unwrap(), Value::Integer(21));
+    assert_eq!(eval("((x, y), z) = ((1, 2), 3); (x, y, z)", &mut ctx).unwrap(), l(vec![1, 2, 3]));
+    assert_eq!(eval("(head, ...tail) = (1, 2, 3); tail", &mut ctx).unwrap(), l(vec![2, 3]));
+    assert_eq!(eval("(first, ...mid, last) = (1, 2); (first, mid, last)", &mut ctx).unwrap(),
+        Value::from(vec![1.into(), l(vec![]), 2.into()]));
+    assert_eq!(eval("map(enumerate((5, 6, 7),), ((n, x),):(n*x))", &mut ctx).unwrap(), l(vec![0, 6, 14]));
+    assert_eq!(eval("(((a, b), c):(a+b+c))((1, 2), 3)", &mut ctx).unwrap(), Value::Integer(6));
+    assert_eq!(eval("count = (f, ...rest):f(rest,); count(len, 1, 2, 3)", &mut ctx).unwrap(), Value::Integer(3));
+    assert!(eval("count()", &mut ctx).is_err());
+    assert!(matches!(eval("(a, b) = (1, 2, 3)", &mut ctx), Err(Error::Eval(_))));
+    assert!(matches!(eval("(a, b) = 5", &mut ctx), Err(Error::Eval(_))));
+    assert!(matches!(eval("(a, 1) = (1, 2)", &mut ctx), Err(Error::Tree(_))));
+    assert!(matches!(eval("(...a, ...b) = (1, 2)", &mut ctx), Err(Error::Tree(_))));
+    assert!(matches!(eval("...a", &mut ctx), Err(Error::Eval(_))));
+}
+
+#[test]
+fn test_params() {
+    use complexpr::*;
+    let mut ctx = complexpr::ctx_default();
+    let l = |x: Vec<i64>| Value::from(x.into_iter().map(Value::from).collect::<Vec<Value>>());
+    eval("f = (x, y=1, z=y*2):(x, y, z)", &mut ctx).unwrap();
+    assert_eq!(eval("f(5)", &mut ctx).unwrap(), l(vec![5, 1, 2]));
+    assert_eq!(eval("f(5, 3)", &mut ctx).unwrap(), l(vec![5, 3, 6]));
+    assert_eq!(eval("f(5, z=0)", &mut ctx).unwrap(), l(vec![5, 1, 0]));
+    assert_eq!(eval("f(y=4, x=2)", &mut ctx).unwrap(), l(vec![2, 4, 8]));
+    assert_eq!(eval("f(y=4, 7)", &mut ctx).unwrap(), l(vec![7, 4, 8]));
+    eval("g = (a, b=10, ...rest):(a + b + len(rest))", &mut ctx).unwrap();
+    assert_eq!(eval("g(1)", &mut ctx).unwrap(), Value::Integer(11));
+    assert_eq!(eval("g(1, 2, 3, 4)", &mut ctx).unwrap(), Value::Integer(5));
+    assert_eq!(eval("((n=3):n)()", &mut ctx).unwrap(), Value::Integer(3));
+    assert_eq!(eval("(a, b=0) = (4,); a + b", &mut ctx).unwrap(), Value::Integer(4));
+    let err = eval("f()", &mut ctx).unwrap_err().to_string();
+    assert!(err.contains("(x, y=1, z=(y * 2))"), "{}", err);
+    let err = eval("f(1, 2, 3, 4)", &mut ctx).unwrap_err().to_string();
+    assert!(err.contains("Too many arguments"), "{}", err);
+    assert!(eval("f(1, w=2)", &mut ctx).is_err());
+    let err = eval("f(1, x=2)", &mut ctx).unwrap_err().to_string();
+    assert!(err.contains("Argument 'x' given more than once"), "{}", err);
+    assert!(eval("f(y=4, 7, 8)", &mut ctx).is_err());
+    assert!(eval("f(1, y=2, y=3)", &mut ctx).is_err());
+    assert_eq!(eval("f(z=0, 5, 3)", &mut ctx).unwrap(), l(vec![5, 3, 0]));
+    assert!(eval("g(1, 2, 3, b=4)", &mut ctx).is_err());
+    assert!(eval("add(1, x=2)", &mut ctx).is_err());
+    assert!(matches!(eval("(x=1, y):x", &mut ctx), Err(Error::Tree(_))));
+}
+
+#[test]
+fn test_spread() {
+    use complexpr::*;
+    let mut ctx = complexpr::ctx_default();
+    let l = |x: Vec<i64>| Value::from(x.into_iter().map(Value::from).collect::<Vec<Value>>());
+    assert_eq!(eval("len((1, 2, 3))", &mut ctx).unwrap(), Value::Integer(3));
+    assert_eq!(eval("sort((3, 1, 2))", &mut ctx).unwrap(), l(vec![1, 2, 3]));
+    assert_eq!(eval("len(())", &mut ctx).unwrap(), Value::Integer(0));
+    assert_eq!(eval("xs = (1, 2, 3); add(...xs)", &mut ctx).unwrap(), Value::Integer(6));
+    assert_eq!(eval("max(0, ...xs, ...range(5))", &mut ctx).unwrap(), Value::Integer(4));
+    assert_eq!(eval("add(1, 2, 3)", &mut ctx).unwrap(), Value::Integer(6));
+    assert!(eval("(...xs)", &mut ctx).is_err());
+    assert!(eval("add(...5)", &mut ctx).is_err());
+    let opts = CompileOptions { unpack_list_args: true, ..Default::default() };
+    assert_eq!(compile_with("add((1, 2, 3))", &opts).unwrap().eval(&mut ctx).unwrap(), Value::Integer(6));
+    assert_eq!(compile_with("len((1, 2, 3),)", &opts).unwrap().eval(&mut ctx).unwrap(), Value::Integer(3));
+}
+
+#[test]
+fn test_pipe() {
+    use complexpr::*;
+    let mut ctx = complexpr::ctx_default();
+    let l = |x: Vec<i64>| Value::from(x.into_iter().map(Value::from).collect::<Vec<Value>>());
+    assert_eq!(eval("(1, 2, 3) |> map(x:(x*2)) |> fold(add)", &mut ctx).unwrap(), Value::Integer(12));
+    assert_eq!(eval("1 + 2 |> sub(1)", &mut ctx).unwrap(), Value::Integer(2));
+    assert_eq!(eval("-4 |> abs", &mut ctx).unwrap(), Value::Integer(4));
+    assert_eq!(eval("3 |> (x:(x^2))", &mut ctx).unwrap(), Value::Float(9.0));
+    eval("inc = x:(x+1); double = x:(x*2)", &mut ctx).unwrap();
+    assert_eq!(eval("(inc >> double)(5)", &mut ctx).unwrap(), Value::Integer(12));
+    assert_eq!(eval("5 |> inc >> double >> inc", &mut ctx).unwrap(), Value::Integer(13));
+    assert_eq!(eval("compose(inc, double)(5)", &mut ctx).unwrap(), Value::Integer(11));
+    assert_eq!(eval("compose(inc, add)(1, 2, 3)", &mut ctx).unwrap(), Value::Integer(7));
+    assert_eq!(eval("partial(sub, 10)(3)", &mut ctx).unwrap(), Value::Integer(7));
+    assert_eq!(eval("flip(sub)(10, 3)", &mut ctx).unwrap(), Value::Integer(-7));
+    assert_eq!(eval("curry(add, 3)(1)(2)(3)", &mut ctx).unwrap(), Value::Integer(6));
+    assert_eq!(eval("curry((a, b, c=0):(a, b, c))(1)(2)", &mut ctx).unwrap(), l(vec![1, 2, 0]));
+    assert_eq!(eval("map((1, 2), partial(mul, 3))", &mut ctx).unwrap(), l(vec![3, 6]));
+    assert!(eval("1 >> inc", &mut ctx).is_err());
+    let err = eval("true >> sin", &mut ctx).unwrap_err().to_string();
+    assert!(err.contains("wrong type"), "{}", err);
+    assert!(eval("false << inc", &mut ctx).is_err());
+    assert!(eval("inc >> true", &mut ctx).is_err());
+    assert!(eval("flip(sub)(1)", &mut ctx).is_err());
+    assert!(matches!(eval("|> f", &mut ctx), Err(Error::Tree(_))));
+}
+
+#[test]
+fn test_compare_chain() {
+    use complexpr::*;
+    let mut ctx = complexpr::ctx_default();
+    assert_eq!(eval("x = 5; 0 <= x < 10", &mut ctx).unwrap(), Value::Bool(true));
+    assert_eq!(eval("0 <= x < 5", &mut ctx).unwrap(), Value::Bool(false));
+    assert_eq!(eval("1 < 2 < 3 < 4 == 4", &mut ctx).unwrap(), Value::Bool(true));
+    assert_eq!(eval("3 > 2 > 2", &mut ctx).unwrap(), Value::Bool(false));
+    // evaluation stops at the first false comparison
+    assert_eq!(eval("5 < 1 < unset_variable", &mut ctx).unwrap(), Value::Bool(false));
+    assert!(eval("1 < 5 < unset_variable", &mut ctx).is_err());
+    // parenthesized comparisons are not chained
+    assert_eq!(eval("(1 < 2) == true", &mut ctx).unwrap(), Value::Bool(true));
+    assert!(eval("(0 <= x) < 10", &mut ctx).is_err());
+    assert!(eval(r#""a" < 1"#, &mut ctx).is_err());
+    assert!(eval("1i > 0", &mut ctx).is_err());
+    assert_eq!(eval("nan < 1", &mut ctx).unwrap(), Value::Bool(false));
+    assert_eq!(eval("(1, 2) < (1, 3)", &mut ctx).unwrap(), Value::Bool(true));
+    assert_eq!(eval(r#"1 == "a""#, &mut ctx).unwrap(), Value::Bool(false));
+    assert_eq!(compile("0 < x <= 1").unwrap().to_string(), "(0 < x <= 1)");
+}
+
+#[test]
+fn test_scope() {
+    use complexpr::*;
+    let mut ctx = complexpr::ctx_default();
+    // new variables in a block are discarded, existing ones are assigned to
+    assert_eq!(eval("x = 1; { y = 2; x = x + y; x * 10 }", &mut ctx).unwrap(), Value::Integer(30));
+    assert_eq!(eval("x", &mut ctx).unwrap(), Value::Integer(3));
+    assert!(eval("y", &mut ctx).is_err());
+    // `let` shadows an outer variable until the end of the block
+    assert_eq!(eval("{ let x = 10; x += 1; x }", &mut ctx).unwrap(), Value::Integer(11));
+    assert_eq!(eval("x", &mut ctx).unwrap(), Value::Integer(3));
+    assert_eq!(eval("{ let (a, b) = (1, 2); { let a = 5; a + b } + a }", &mut ctx).unwrap(), Value::Integer(8));
+    assert_eq!(eval("{}", &mut ctx).unwrap(), Value::Void);
+    assert_eq!(eval("{1, 2}", &mut ctx).unwrap(), Value::List(vec![Value::Integer(1), Value::Integer(2)]));
+    // lambda parameters and locals do not leak into the caller
+    assert_eq!(eval("f = n:(tmp = n * 2; tmp + x); f(1)", &mut ctx).unwrap(), Value::Integer(5));
+    assert!(eval("tmp", &mut ctx).is_err());
+    assert!(eval("n", &mut ctx).is_err());
+    assert_eq!(eval("$unset(\"x\"); $is_set(\"x\")", &mut ctx).unwrap(), Value::Bool(false));
+    assert!(eval("{ 1", &mut ctx).is_err());
+    assert!(eval("( 1 }", &mut ctx).is_err());
+    assert!(eval("{1} {2}", &mut ctx).is_err());
+    assert!(eval("let x + 1", &mut ctx).is_err());
+    assert_eq!(compile("{let x = 1; x}").unwrap().to_string(), "{(let x = 1; x)}");
+}
+
+#[test]
+fn test_closures() {
+    use complexpr::*;
+    let mut ctx = complexpr::ctx_default();
+    // lambdas see later changes to the variables they use
+    assert_eq!(eval("k = 2; scale = x:(k * x); k = 3; scale(5)", &mut ctx).unwrap(), Value::Integer(15));
+    // but assignments inside them define variables of the call, so state goes through references
+    assert_eq!(eval("x = 1; f = ():(x = 5; x); (f(), x)", &mut ctx).unwrap(), Value::from(vec![Value::Integer(5), Value::Integer(1)]));
+    assert_eq!(eval("j = 7; map((1, 2), v:(j = v)); j", &mut ctx).unwrap(), Value::Integer(7));
+    assert_eq!(eval("f = x:{ x += 1; let y = 1; { x += 1; y += x }; y }; f(1)", &mut ctx).unwrap(), Value::Integer(4));
+    eval("make_counter = :(let n = ref(0); :update(n, add, 1)); c1 = make_counter(); c2 = make_counter()", &mut ctx).unwrap();
+    assert_eq!(eval("c1(); c1(); c1()", &mut ctx).unwrap(), Value::Integer(3));
+    assert_eq!(eval("c2()", &mut ctx).unwrap(), Value::Integer(1));
+    eval("total = ref(0); map(range(5), x:(total := deref(total) + x)) |> collect", &mut ctx).unwrap();
+    assert_eq!(eval("deref(total)", &mut ctx).unwrap(), Value::Integer(10));
+    // a lambda can call itself through the variable it is assigned to
+    assert_eq!(eval("fact = n:((n <= 1)(:1, :(n * fact(n - 1)))()); fact(10)", &mut ctx).unwrap(), Value::Integer(3628800));
+    assert_eq!(eval(r#"g = :$get("k"); g()"#, &mut ctx).unwrap(), Value::Integer(3));
+    // contexts created from the default context are independent
+    let mut other = complexpr::ctx_default();
+    assert!(eval("k", &mut other).is_err());
+    eval("sin = 1", &mut other).unwrap();
+    assert!(eval("sin(0)", &mut ctx).is_ok());
+    // a clone is a copy, whose lambdas use the copied variables, though references are shared
+    let mut snapshot = ctx.clone();
+    eval("k = 10; c1()", &mut ctx).unwrap();
+    assert_eq!(eval("(scale(1), c1())", &mut snapshot).unwrap(), Value::from(vec![Value::Integer(3), Value::Integer(5)]));
+    assert_eq!(eval("(scale(1), c1())", &mut ctx).unwrap(), Value::from(vec![Value::Integer(10), Value::Integer(6)]));
+    let mut shared = ctx.share();
+    eval("k = 20", &mut shared).unwrap();
+    assert_eq!(eval("scale(1)", &mut ctx).unwrap(), Value::Integer(20));
+}
+
+#[test]
+fn test_closure_frames_freed() {
+    use complexpr::*;
+    for expr in [
+        "f = x:(x + pi + 0*len(deref(cell))); f(1)",
+        "fact = n:((n <= 1)(:1, :(n * fact(n - 1)))()); fact(5)",
+        "fs = (x:(x + pi), :1); index(fs, 0)(1)",
+        "g = :{ let c = cell; let h = :(c); h() }; g()",
+        "{ let c = cell; let hs = (:(c), :2); index(hs, 0)() }",
+        "make = :(let c = cell; let h = :(c); h); h = make(); h()",
+        "a = :(b()); b = :(cell); a()",
+    ] {
+        let mut ctx = ctx_default();
+        let cell = std::sync::Arc::new(std::sync::Mutex::new(Value::from(vec![])));
+        ctx.insert("cell".to_owned(), Value::Ref(cell.clone()));
+        eval(expr, &mut ctx).unwrap();
+        let copy = ctx.clone();
+        assert!(eval("h = 0; f = 0; 1", &mut copy.share()).is_ok());
+        drop(copy);
+        drop(ctx);
+        assert_eq!(std::sync::Arc::strong_count(&cell), 1, "{}", expr);
+    }
+}
+
+#[test]
+fn test_ref_cycle_frames_kept() {
+    use complexpr::*;
+    // a reference holding a lambda that uses the reference is a cycle, which is never freed
+    let mut ctx = ctx_default();
+    let cell = std::sync::Arc::new(std::sync::Mutex::new(Value::from(vec![])));
+    ctx.insert("cell".to_owned(), Value::Ref(cell.clone()));
+    eval("r = ref(0); r := :(len(deref(cell)) + deref(r)()); 1", &mut ctx).unwrap();
+    drop(ctx);
+    assert!(std::sync::Arc::strong_count(&cell) > 1);
+}
+
+#[test]
+fn test_optimize() {
+    use complexpr::*;
+    let mut ctx = complexpr::ctx_default();
+    let both = |expr: &str, ctx: &mut Context| {
+        let plain = compile(expr).unwrap().eval(&mut ctx.clone());
+        let optimized = compile_optimized(expr).unwrap().eval(&mut ctx.clone());
+        assert_eq!(plain.is_ok(), optimized.is_ok(), "{}", expr);
+        if let (Ok(a), Ok(b)) = (&plain, &optimized) {
+            assert!(a.is_callable() || a == b, "{}", expr);
+        }
+        optimized
+    };
+    assert_eq!(compile_optimized("2*3 + x").unwrap().to_string(), compile("6 + x").unwrap().to_string());
+    assert_eq!(compile_optimized("sqrt(16) * x").unwrap().to_string(), compile("4.0 * x").unwrap().to_string());
+    assert_eq!(compile_optimized("(true)(1, 2)").unwrap().to_string(), "1");
+    assert_eq!(compile_optimized("1; 2; x").unwrap().to_string(), "x");
+    assert_eq!(compile("2*3").unwrap().to_string(), "(2 * 3)");
+    // shadowed builtins are not replaced
+    assert_eq!(both("sin = x:(x + 1); sin(1)", &mut ctx).unwrap(), Value::Integer(2));
+    assert_eq!(both("f = sin:sin(1); f(x:(x*2))", &mut ctx).unwrap(), Value::Integer(2));
+    assert_eq!(both("$set(\"cos\", 0); cos", &mut ctx).unwrap(), Value::Integer(0));
+    eval("x = 5", &mut ctx).unwrap();
+    assert_eq!(both("(x > 3)(:\"big\", :\"small\")()", &mut ctx).unwrap(), Value::from("big"));
+    assert_eq!(both("y = 0; (1 < 2)(:(y = 1), :(y = 2))(); y", &mut ctx).unwrap(), Value::Integer(0));
+    assert_eq!(both("y = 0; (1 < 2)(:(y = 1; y), :2)()", &mut ctx).unwrap(), Value::Integer(1));
+    assert_eq!(both("y = ref(0); (1 < 2)(:(y := 1), :2)(); deref(y)", &mut ctx).unwrap(), Value::Integer(1));
+    assert_eq!(both("{ let x = 2*pi; x / pi }", &mut ctx).unwrap(), Value::Float(2.0));
+    assert_eq!(both("r = ref(0); (false)(update(r, add, 1), 2); deref(r)", &mut ctx).unwrap(), Value::Integer(1));
+    assert!(both("1/0; x", &mut ctx).is_err());
+    assert!(both("sqrt(1, 2)", &mut ctx).is_err());
+    assert_eq!(both("fact = n:((n <= 1)(:1, :(n * fact(n - 1)))()); fact(5)", &mut ctx).unwrap(), Value::Integer(120));
+}
@@ -0,0 +1,97 @@
+use complexpr::*;
+
+/// Evaluates each expression with the tree-walking evaluator and with the VM, in separate
+/// contexts that start out the same, and checks that the results and messages agree
+fn check_same(exprs: &[&str]) {
+    let mut tree_ctx = ctx_default();
+    let mut vm_ctx = ctx_default();
+    for expr in exprs {
+        let node = compile(expr).unwrap();
+        let tree = node.eval(&mut tree_ctx).map(|v| format!("{:?}", v)).map_err(|e| e.to_string());
+        let vm = vm::Program::compile(&node).eval(&mut vm_ctx).map(|v| format!("{:?}", v)).map_err(|e| e.to_string());
+        assert_eq!(tree, vm, "in {}", expr);
+    }
+    let mut tree_vars = tree_ctx.iter().filter(|(_, v)| !v.is_callable()).map(|(k, v)| format!("{}={:?}", k, v)).collect::<Vec<_>>();
+    let mut vm_vars = vm_ctx.iter().filter(|(_, v)| !v.is_callable()).map(|(k, v)| format!("{}={:?}", k, v)).collect::<Vec<_>>();
+    tree_vars.sort();
+    vm_vars.sort();
+    assert_eq!(tree_vars, vm_vars);
+}
+
+#[test]
+fn same_as_tree() {
+    check_same(&[
+        "1 + 2 * 3 ^ 2",
+        "x = 3; y = x * 2; x += y; (x, y)",
+        "-x + ~4 - 2 // 3",
+        "sin(pi / 2) + max(1, 5, 3)",
+        "f\"{x} and {y:08.3}!\"",
+        "1 < x <= 9 < 10",
+        "5 < 1 < unset_variable",
+        "1 < 5 < unset_variable",
+        "z += 1",
+        "$x = 1",
+        "(a, b) = (1, 2); a + b",
+        "{ let a = 10; a } + a",
+        "k = 2; scale = v:(k * v); k = 3; scale(5)",
+        "total = 0; map(range(5), v:(total += v)) |> collect; total",
+        "g = :(total = 100); g(); total + 1",
+        "$set(\"w\", 7); w * 2",
+        "named = (p, q=1):(p - q); named(5, q=2) + named(...(3, 1))",
+        "1 + \"a\"",
+        "r = ref(1); r := 2; deref(r)",
+    ]);
+}
+
+#[test]
+fn slots() {
+    let mut ctx = ctx_default();
+    eval("a = 2; b = -3; c = 1; f = v:(v + a)", &mut ctx).unwrap();
+    let program = compile_bytecode("a*x^2 + b*x + c").unwrap();
+    let x = program.slot("x").unwrap();
+    let mut vm = program.vm(&ctx);
+    assert!(vm.run().is_err());
+    for n in 0..5 {
+        vm.set(x, Value::Integer(n));
+        assert_eq!(vm.run().unwrap(), Value::Integer(2*n*n - 3*n + 1));
+    }
+    // changes to the context are seen by the next run
+    eval("a = 0", &mut ctx).unwrap();
+    assert_eq!(vm.run().unwrap(), Value::Integer(-11));
+    // values set on the machine are visible to functions it calls
+    let program = compile_bytecode("f(a) + f(x)").unwrap();
+    let mut vm = program.vm(&ctx);
+    vm.set(program.slot("a").unwrap(), Value::Integer(10));
+    vm.set(program.slot("x").unwrap(), Value::Integer(1));
+    assert_eq!(vm.run().unwrap(), Value::Integer(31));
+    assert_eq!(eval("(a, x)", &mut ctx).unwrap(), Value::List(vec![Value::Integer(10), Value::Integer(1)]));
+    // assignments are written to the context
+    let program = compile_bytecode("n = x * 2").unwrap();
+    program.eval(&mut ctx).unwrap();
+    assert_eq!(eval("n", &mut ctx).unwrap(), Value::Integer(2));
+}
+
+#[test]
+fn params() {
+    let mut ctx = ctx_default();
+    eval("a = 2; b = -3; c = 1; x = 100", &mut ctx).unwrap();
+    let mut f = compile_params("a*x^2 + b*x + c", &["x"], &ctx).unwrap();
+    for n in 0..5 {
+        assert_eq!(f.call(&[Value::Integer(n)]).unwrap(), Value::Integer(2*n*n - 3*n + 1));
+        assert_eq!(f.call_f64(&[n as f64 / 2.0]).unwrap(), 0.5*(n*n) as f64 - 1.5*n as f64 + 1.0);
+    }
+    // parameters shadow the context's variables without changing them
+    assert_eq!(eval("x", &mut ctx).unwrap(), Value::Integer(100));
+    eval("c = 10", &mut ctx).unwrap();
+    assert_eq!(f.call_f64(&[0.0]).unwrap(), 10.0);
+    assert!(f.call(&[]).is_err());
+    assert!(f.call_f64(&[1.0, 2.0]).is_err());
+    // parameters are visible to lambdas, and unused parameters are allowed
+    let mut g = compile_params("map((1, 2), v:(v * k)) |> collect", &["k", "unused"], &ctx).unwrap();
+    let l = |x: Vec<i64>| Value::List(x.into_iter().map(Value::Integer).collect());
+    assert_eq!(g.call(&[Value::Integer(3), Value::Void]).unwrap(), l(vec![3, 6]));
+    assert!(g.call_f64(&[3.0, 0.0]).is_err());
+    let mut h = compile_params("y // 2", &["y"], &ctx).unwrap();
+    assert_eq!(h.call(&[Value::Integer(3)]).unwrap().to_string(), "3//2");
+    assert_eq!(compile_params("1 // 4", &[], &ctx).unwrap().call_f64(&[]).unwrap(), 0.25);
+}